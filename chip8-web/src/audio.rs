@@ -0,0 +1,139 @@
+use std::{cell::RefCell, rc::Rc};
+
+use wasm_bindgen::prelude::*;
+use web_sys::{AudioContext, Event, GainNode, HtmlInputElement, OscillatorType};
+
+use crate::dom::document;
+
+/// Pitch of the beep in Hz, same as the default of the core audio generator
+const BEEP_FREQUENCY: f32 = 440.0;
+
+/// Time constant of the volume changes in seconds, keeps the beep free of clicks
+const FADE_SECONDS: f64 = 0.005;
+
+/// Plays the sound timer beep through WebAudio
+///
+/// Browsers only allow audio after a user gesture, so the `AudioContext` is
+/// created on the first click or key press on the page.
+pub struct Beeper {
+    output: Option<(AudioContext, GainNode)>,
+    active: bool,
+    pub muted: bool,
+
+    /// Volume between 0.0 and 1.0
+    pub volume: f32,
+}
+
+impl Default for Beeper {
+    fn default() -> Self {
+        Self {
+            output: None,
+            active: false,
+            muted: false,
+            volume: 0.25,
+        }
+    }
+}
+
+impl Beeper {
+    /// Creates the audio graph: a square wave oscillator running through a gain node
+    fn start(&mut self) -> Result<(), JsValue> {
+        if self.output.is_some() {
+            return Ok(());
+        }
+
+        let ctx = AudioContext::new()?;
+
+        let oscillator = ctx.create_oscillator()?;
+        oscillator.set_type(OscillatorType::Square);
+        oscillator.frequency().set_value(BEEP_FREQUENCY);
+
+        let gain = ctx.create_gain()?;
+        gain.gain().set_value(0.0);
+
+        oscillator.connect_with_audio_node(&gain)?;
+        gain.connect_with_audio_node(&ctx.destination())?;
+        oscillator.start()?;
+
+        let _ = ctx.resume()?;
+
+        self.output = Some((ctx, gain));
+        self.apply();
+        Ok(())
+    }
+
+    /// Sets whether the beep should be audible, has to be called every frame
+    /// with the state of the sound timer
+    pub fn update(&mut self, sound_timer: u8) {
+        let active = sound_timer > 0;
+        if active != self.active {
+            self.active = active;
+            self.apply();
+        }
+    }
+
+    /// Fades the gain to the current target volume
+    fn apply(&self) {
+        if let Some((ctx, gain)) = &self.output {
+            let target = if self.active && !self.muted { self.volume } else { 0.0 };
+            gain.gain()
+                .set_target_at_time(target, ctx.current_time(), FADE_SECONDS)
+                .unwrap();
+        }
+    }
+}
+
+/// Starts the audio on the first user interaction and hooks up the
+/// `#mute` and `#volume` controls
+pub fn init_audio(beeper: Rc<RefCell<Beeper>>) {
+    let unlock = {
+        let beeper = beeper.clone();
+        Closure::<dyn FnMut(Event)>::new(move |_: Event| {
+            if let Err(e) = beeper.borrow_mut().start() {
+                web_sys::console::error_2(&JsValue::from_str("could not start audio:"), &e);
+            }
+        })
+    };
+
+    for event in ["pointerdown", "keydown"] {
+        document()
+            .add_event_listener_with_callback(event, unlock.as_ref().unchecked_ref())
+            .unwrap();
+    }
+    unlock.forget();
+
+    let mute = input("mute");
+    let on_mute = {
+        let (beeper, mute) = (beeper.clone(), mute.clone());
+        Closure::<dyn FnMut(Event)>::new(move |_: Event| {
+            let mut beeper = beeper.borrow_mut();
+            beeper.muted = mute.checked();
+            beeper.apply();
+        })
+    };
+    mute.add_event_listener_with_callback("change", on_mute.as_ref().unchecked_ref())
+        .unwrap();
+    on_mute.forget();
+
+    let volume = input("volume");
+    let on_volume = {
+        let volume = volume.clone();
+        Closure::<dyn FnMut(Event)>::new(move |_: Event| {
+            let mut beeper = beeper.borrow_mut();
+            beeper.volume = volume.value_as_number() as f32 / 100.0;
+            beeper.apply();
+        })
+    };
+    volume
+        .add_event_listener_with_callback("input", on_volume.as_ref().unchecked_ref())
+        .unwrap();
+    on_volume.forget();
+}
+
+fn input(id: &str) -> HtmlInputElement {
+    document()
+        .get_element_by_id(id)
+        .unwrap_or_else(|| panic!("no {} element", id))
+        .dyn_into::<HtmlInputElement>()
+        .unwrap()
+}
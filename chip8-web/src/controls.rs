@@ -0,0 +1,131 @@
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
+
+use chip8::{
+    app::{AppState, ExecError},
+    debug::Debugger,
+};
+use web_sys::Element;
+
+use crate::{
+    dom::{element, on_click},
+    loader::RomLoader,
+    timing::{IpsCounter, Speed},
+};
+
+/// Hooks up the `#run`, `#step`, `#step-frame` and `#reset` buttons
+///
+/// Stepping pauses the emulation, the frame loop only runs the emulator while
+/// `paused` is false and keeps the labels in sync through [StatusBar].
+/// A step that fails leaves its error in `error` for the status bar.
+pub fn init_controls(
+    loader: RomLoader,
+    paused: Rc<Cell<bool>>,
+    speed: Rc<Cell<Speed>>,
+    debugger: Rc<RefCell<Debugger>>,
+    error: Rc<RefCell<Option<ExecError>>>,
+) {
+    on_click(&element("run"), {
+        let paused = paused.clone();
+        move || paused.set(!paused.get())
+    });
+
+    on_click(&element("step"), {
+        let (app, paused, error) = (loader.app.clone(), paused.clone(), error.clone());
+        move || {
+            paused.set(true);
+            if let Err(e) = app.borrow_mut().try_step() {
+                error.replace(Some(e));
+            }
+        }
+    });
+
+    on_click(&element("step-frame"), {
+        let app = loader.app.clone();
+        move || {
+            paused.set(true);
+            // Breakpoints end the frame early, same as when running
+            let end = debugger
+                .borrow()
+                .run_frame(&mut app.borrow_mut(), speed.get().cycles_per_frame);
+            if let Err(e) = end {
+                error.replace(Some(e));
+            }
+        }
+    });
+
+    on_click(&element("reset"), move || loader.reset());
+}
+
+/// Shows the next instruction, the registers and the speed in `#status`
+/// and labels `#run` with what it does
+pub struct StatusBar {
+    element: Element,
+    run: Element,
+    text: String,
+    pub ips: IpsCounter,
+    /// The error that paused the emulation, shown until it runs again
+    pub error: Rc<RefCell<Option<ExecError>>>,
+}
+
+impl StatusBar {
+    pub fn new() -> Self {
+        Self {
+            element: element("status"),
+            run: element("run"),
+            text: String::new(),
+            ips: IpsCounter::default(),
+            error: Rc::default(),
+        }
+    }
+
+    pub fn update(&mut self, app: &AppState, paused: bool) {
+        if !paused {
+            self.error.replace(None);
+        }
+        let text = if let Some(error) = &*self.error.borrow() {
+            format!(
+                "{} | PC 0x{:03X} | I 0x{:03X} | SP {} | {}",
+                app.current_op(),
+                app.pc,
+                app.I,
+                app.sp,
+                error
+            )
+        } else if paused {
+            format!(
+                "{} | PC 0x{:03X} | I 0x{:03X} | SP {} | paused",
+                app.current_op(),
+                app.pc,
+                app.I,
+                app.sp
+            )
+        } else {
+            format!(
+                "{} | PC 0x{:03X} | I 0x{:03X} | SP {} | {} IPS",
+                app.current_op(),
+                app.pc,
+                app.I,
+                app.sp,
+                self.ips.ips
+            )
+        };
+
+        // Most frames don't change anything while paused
+        if text != self.text {
+            self.element.set_text_content(Some(&text));
+            self.run.set_text_content(Some(run_label(paused)));
+            self.text = text;
+        }
+    }
+}
+
+fn run_label(paused: bool) -> &'static str {
+    if paused {
+        "Run"
+    } else {
+        "Pause"
+    }
+}
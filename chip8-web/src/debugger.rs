@@ -0,0 +1,181 @@
+use std::{
+    cell::{Cell, RefCell},
+    fmt::Write,
+    rc::Rc,
+};
+
+use chip8::{
+    app::AppState,
+    chip8::{ch8_types::MEMORY_SIZE, Ops},
+    debug::{disassemble, Debugger},
+};
+use wasm_bindgen::prelude::*;
+use web_sys::{Element, HtmlDetailsElement};
+
+use crate::dom::{document, element, on_click};
+
+/// Instructions shown in the disassembly
+const DISASSEMBLY_ROWS: usize = 16;
+
+/// Instructions shown above PC, so the surrounding code stays visible
+const ROWS_BEFORE_PC: usize = 5;
+
+/// Rows of 16 bytes shown in the memory viewer
+const MEMORY_ROWS: usize = 8;
+const MEMORY_ROW_SIZE: usize = 16;
+
+/// The debugger view inside `#debugger`: registers, disassembly and memory
+///
+/// Nothing is updated while the `<details>` element is closed.
+pub struct DebuggerPanel {
+    panel: HtmlDetailsElement,
+    registers: Element,
+    rows: Vec<Element>,
+    memory: Element,
+
+    /// Address of the first disassembly row, the rows are two bytes apart
+    start: Rc<Cell<usize>>,
+    pub debugger: Rc<RefCell<Debugger>>,
+}
+
+impl DebuggerPanel {
+    /// Builds the disassembly rows, clicking a row toggles its breakpoint
+    pub fn new() -> Self {
+        let disassembly = element("dbg-disassembly");
+        let start = Rc::new(Cell::new(0));
+        let debugger = Rc::new(RefCell::new(Debugger::default()));
+
+        let mut rows = Vec::new();
+        for i in 0..DISASSEMBLY_ROWS {
+            let row = document().create_element("div").unwrap();
+            on_click(&row, {
+                let (start, debugger, row) = (start.clone(), debugger.clone(), row.clone());
+                move || {
+                    let set = debugger.borrow_mut().toggle_breakpoint(start.get() + i * 2);
+                    let _ = row.class_list().toggle_with_force("breakpoint", set);
+                }
+            });
+            disassembly.append_child(&row).unwrap();
+            rows.push(row);
+        }
+
+        Self {
+            panel: element("debugger").dyn_into::<HtmlDetailsElement>().unwrap(),
+            registers: element("dbg-registers"),
+            rows,
+            memory: element("dbg-memory"),
+            start,
+            debugger,
+        }
+    }
+
+    pub fn update(&self, app: &AppState) {
+        if !self.panel.open() {
+            return;
+        }
+
+        self.registers.set_text_content(Some(&registers(app)));
+        self.update_disassembly(app);
+        self.memory.set_inner_html(&memory_html(app));
+    }
+
+    fn update_disassembly(&self, app: &AppState) {
+        let start = app.pc.saturating_sub(ROWS_BEFORE_PC * 2);
+        self.start.set(start);
+
+        let memory = app.memory();
+        let debugger = self.debugger.borrow();
+        let mut ops = disassemble(memory, start);
+        for (i, row) in self.rows.iter().enumerate() {
+            let address = start + i * 2;
+            let text = match ops.next() {
+                Some((_, op)) => format!("{:03X}  {:02X}{:02X}  {}", address, memory[address], memory[address + 1], op),
+                None => String::new(),
+            };
+            row.set_text_content(Some(&text));
+
+            let classes = row.class_list();
+            let _ = classes.toggle_with_force("current", address == app.pc);
+            let _ = classes.toggle_with_force("breakpoint", debugger.has_breakpoint(address));
+        }
+    }
+}
+
+fn registers(app: &AppState) -> String {
+    let mut text = String::new();
+    for (i, v) in app.registers().iter().enumerate() {
+        let separator = if i % 4 == 3 { '\n' } else { ' ' };
+        let _ = write!(text, "V{:X} {:02X}{}", i, v, separator);
+    }
+
+    let _ = writeln!(text, "I  {:03X} PC {:03X} SP {}", app.I, app.pc, app.sp);
+    let _ = writeln!(text, "DT {:02X}  ST {:02X}", app.delay_timer, app.sound_timer);
+
+    text.push_str("Stack");
+    for address in app.stack().iter().rev() {
+        let _ = write!(text, " {:03X}", address);
+    }
+    text
+}
+
+/// Number of bytes the instruction reads or writes at I, `DRW` with a height of 0
+/// draws nothing
+fn bytes_at_i(op: &Ops) -> usize {
+    match op {
+        Ops::DRW(_, _, n) => *n as usize,
+        Ops::LDI(x) | Ops::LDVI(x) => x + 1,
+        Ops::LDB(_) => 3,
+        Ops::AUDIO => 16,
+        _ => 1,
+    }
+}
+
+/// Hex dump of the memory around I, the bytes the next instruction accesses at I are highlighted
+fn memory_html(app: &AppState) -> String {
+    let i = app.I as usize;
+    let highlight = i..i + bytes_at_i(&app.current_op());
+
+    let start = (i / MEMORY_ROW_SIZE)
+        .saturating_sub(1)
+        .min(MEMORY_SIZE / MEMORY_ROW_SIZE - MEMORY_ROWS)
+        * MEMORY_ROW_SIZE;
+
+    let mut html = String::new();
+    for row in (start..start + MEMORY_ROWS * MEMORY_ROW_SIZE).step_by(MEMORY_ROW_SIZE) {
+        let _ = write!(html, "{:03X} ", row);
+        for (address, byte) in app.memory()[row..row + MEMORY_ROW_SIZE].iter().enumerate() {
+            if highlight.contains(&(row + address)) {
+                let _ = write!(html, " <mark>{:02X}</mark>", byte);
+            } else {
+                let _ = write!(html, " {:02X}", byte);
+            }
+        }
+        html.push('\n');
+    }
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use chip8::app::AppState;
+
+    use super::memory_html;
+
+    #[test]
+    fn memory_highlight() {
+        // A2A0 LD I, 0x2A0
+        // D015 DRW V0, V1, 5
+        let mut app = AppState::new(&[0xA2, 0xA0, 0xD0, 0x15]);
+        app.step();
+
+        let html = memory_html(&app);
+        assert!(html.starts_with("290 "));
+        assert_eq!(5, html.matches("<mark>").count());
+        assert!(html.contains("\n2A0  <mark>00</mark>"));
+
+        // D010 DRW V0, V1, 0
+        let mut app = AppState::new(&[0xA2, 0xA0, 0xD0, 0x10]);
+        app.step();
+        assert_eq!(0, memory_html(&app).matches("<mark>").count());
+    }
+}
@@ -1,6 +1,15 @@
-use chip8::chip8::ch8_types::{DISPLAY_HEIGHT, DISPLAY_WIDTH, VRAM};
-use wasm_bindgen::prelude::*;
-use web_sys::{CanvasRenderingContext2d, Document, HtmlCanvasElement, Window};
+use std::rc::Rc;
+
+use chip8::{
+    chip8::ch8_types::{DISPLAY_HEIGHT, DISPLAY_WIDTH, VRAM},
+    phosphor::{Intensity, PhosphorMode},
+    render::{planes_from_vram, render, render_planes, Filter, Palette},
+};
+use wasm_bindgen::{prelude::*, Clamped};
+use web_sys::{
+    js_sys::Uint8Array, CanvasRenderingContext2d, Document, Element, Event, File, FileReader, HtmlCanvasElement,
+    HtmlSelectElement, ImageData, Window,
+};
 
 pub fn window() -> Window {
     web_sys::window().expect("no global `window` exists")
@@ -10,6 +19,53 @@ pub fn document() -> Document {
     window().document().expect("no global document exists")
 }
 
+/// Returns the element with the given id, panics if the page doesn't have it
+pub fn element(id: &str) -> Element {
+    document()
+        .get_element_by_id(id)
+        .unwrap_or_else(|| panic!("no {} element", id))
+}
+
+/// Calls `callback` whenever the element is clicked
+pub fn on_click(element: &Element, mut callback: impl FnMut() + 'static) {
+    let listener = Closure::<dyn FnMut(Event)>::new(move |_: Event| callback());
+    element
+        .add_event_listener_with_callback("click", listener.as_ref().unchecked_ref())
+        .unwrap();
+    listener.forget();
+}
+
+/// Reads the file asynchronously and calls `on_load` with its content
+pub fn read_file(file: &File, on_load: impl FnOnce(Vec<u8>) + 'static) -> Result<(), JsValue> {
+    let reader = FileReader::new()?;
+
+    let listener = {
+        let reader = reader.clone();
+        Closure::once(move |_: Event| {
+            if let Ok(buffer) = reader.result() {
+                on_load(Uint8Array::new(&buffer).to_vec());
+            }
+        })
+    };
+    reader.set_onload(Some(listener.as_ref().unchecked_ref()));
+    listener.forget();
+
+    reader.read_as_array_buffer(file)
+}
+
+/// Presses (true) or releases (false) a key of the hex keypad
+pub type KeyHandler = Rc<dyn Fn(u8, bool)>;
+
+/// Callback of `requestAnimationFrame`, receives the current time in milliseconds
+pub type FrameCallback = Closure<dyn FnMut(f64)>;
+
+/// Calls `callback` before the next repaint
+pub fn request_animation_frame(callback: &FrameCallback) {
+    window()
+        .request_animation_frame(callback.as_ref().unchecked_ref())
+        .expect("requestAnimationFrame failed");
+}
+
 pub fn write_to_output_window(data: String) {
     let elem = document()
         .query_selector("pre#output")
@@ -18,35 +74,127 @@ pub fn write_to_output_window(data: String) {
     elem.unwrap().set_inner_html(data.as_str());
 }
 
-pub fn update_canvas(data: &VRAM) {
-    let canvas = document().get_element_by_id("canvas").unwrap();
-    let canvas: HtmlCanvasElement = canvas
-        .dyn_into::<HtmlCanvasElement>()
-        .map_err(|_| ())
-        .unwrap();
+/// Fills the palette `<select>` with the presets and calls `on_change`
+/// with the chosen palette whenever the selection changes
+pub fn init_palette_select(mut on_change: impl FnMut(Palette) + 'static) {
+    init_preset_select("palette", Palette::PRESETS.iter().map(|(name, _)| *name), move |name| {
+        if let Some(palette) = Palette::from_name(name) {
+            on_change(palette);
+        }
+    });
+}
+
+/// Fills the `#filter` select with the upscaling filters and calls `on_change`
+/// with the chosen filter whenever the selection changes
+pub fn init_filter_select(mut on_change: impl FnMut(Filter) + 'static) {
+    init_preset_select("filter", Filter::PRESETS.iter().map(|(name, _)| *name), move |name| {
+        if let Some(filter) = Filter::from_name(name) {
+            on_change(filter);
+        }
+    });
+}
+
+/// Fills the `#phosphor` select with the persistence modes and calls `on_change`
+/// with the chosen mode whenever the selection changes
+pub fn init_phosphor_select(mut on_change: impl FnMut(PhosphorMode) + 'static) {
+    init_preset_select("phosphor", PhosphorMode::PRESETS.iter().map(|(name, _)| *name), move |name| {
+        if let Some(mode) = PhosphorMode::from_name(name) {
+            on_change(mode);
+        }
+    });
+}
 
-    let ctx = canvas
-        .get_context("2d")
-        .unwrap()
-        .unwrap()
-        .dyn_into::<CanvasRenderingContext2d>()
+fn init_preset_select<'a>(
+    id: &str,
+    names: impl Iterator<Item = &'a str>,
+    mut on_change: impl FnMut(&str) + 'static,
+) {
+    let select = element(id).dyn_into::<HtmlSelectElement>().unwrap();
+
+    for name in names {
+        let option = document().create_element("option").unwrap();
+        option.set_attribute("value", name).unwrap();
+        option.set_text_content(Some(name));
+        select.append_child(&option).unwrap();
+    }
+
+    let target = select.clone();
+    let listener = Closure::<dyn FnMut(Event)>::new(move |_: Event| on_change(&target.value()));
+
+    select
+        .add_event_listener_with_callback("change", listener.as_ref().unchecked_ref())
         .unwrap();
 
-    let mut y = 0;
-    while y < DISPLAY_HEIGHT {
-        let mut x = 0;
-        while x < DISPLAY_WIDTH {
-            ctx.set_fill_style_str(
-
-                if data[y][x] {
-                    "white"
-                } else {
-                    "black"
-                }
-            );
-            ctx.fill_rect((x * 10) as f64, (y * 10) as f64, 10_f64, 10_f64);
-            x += 1;
+    listener.forget();
+}
+
+/// Presents frames on a canvas at their native or filtered resolution, CSS scales them up
+///
+/// The context and the RGBA buffer are kept between frames, so presenting a frame
+/// is a single `putImageData` without allocations.
+pub struct Screen {
+    canvas: HtmlCanvasElement,
+    ctx: CanvasRenderingContext2d,
+    planes: Vec<u8>,
+    buffer: Vec<u8>,
+}
+
+impl Screen {
+    pub fn new(canvas: HtmlCanvasElement) -> Self {
+        let ctx = canvas
+            .get_context("2d")
+            .unwrap()
+            .expect("no 2d context")
+            .dyn_into::<CanvasRenderingContext2d>()
+            .unwrap();
+
+        Self {
+            canvas,
+            ctx,
+            planes: Vec::new(),
+            buffer: Vec::new(),
         }
-        y += 1;
+    }
+
+    pub fn present_vram(&mut self, vram: &VRAM, palette: &Palette) {
+        let mut planes = std::mem::take(&mut self.planes);
+        planes.resize(DISPLAY_WIDTH * DISPLAY_HEIGHT, 0);
+        planes_from_vram(vram, &mut planes);
+
+        self.present(&planes, DISPLAY_WIDTH, DISPLAY_HEIGHT, palette);
+        self.planes = planes;
+    }
+
+    /// Upscales the frame with the filter, the canvas gets the size of the filtered frame
+    pub fn present_frame(&mut self, frame: &Intensity, filter: Filter, palette: &Palette) {
+        let (width, height) = filter.output_size();
+        self.resize(width, height);
+
+        self.buffer.resize(filter.buffer_len(), 0);
+        render(frame, filter, palette, &mut self.buffer);
+        self.put_buffer(width, height);
+    }
+
+    /// Draws a frame with one byte of bit planes per pixel, the canvas is
+    /// resized when the resolution changes, e.g. for SUPER-CHIP hires mode
+    pub fn present(&mut self, planes: &[u8], width: usize, height: usize, palette: &Palette) {
+        self.resize(width, height);
+
+        self.buffer.resize(width * height * 4, 0);
+        render_planes(planes, palette, &mut self.buffer);
+        self.put_buffer(width, height);
+    }
+
+    fn resize(&self, width: usize, height: usize) {
+        if self.canvas.width() as usize != width || self.canvas.height() as usize != height {
+            self.canvas.set_width(width as u32);
+            self.canvas.set_height(height as u32);
+        }
+    }
+
+    fn put_buffer(&self, width: usize, height: usize) {
+        let image =
+            ImageData::new_with_u8_clamped_array_and_sh(Clamped(&self.buffer), width as u32, height as u32).unwrap();
+        self.ctx.put_image_data(&image, 0.0, 0.0).unwrap();
     }
 }
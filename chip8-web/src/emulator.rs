@@ -0,0 +1,156 @@
+use chip8::{
+    app::{AppState, ExecError},
+    chip8::ch8_types::{DISPLAY_HEIGHT, DISPLAY_WIDTH},
+};
+use wasm_bindgen::prelude::*;
+
+use crate::{utils::js_error, CYCLES_PER_FRAME};
+
+/// The emulator core for embedding into any page, independent of the DOM
+///
+/// ```js
+/// const emu = new Emulator(rom);
+/// emu.key_down(0x5);
+/// try {
+///     emu.run_frame();
+/// } catch (e) {
+///     console.error(e); // "unsupported instruction at 0x200 (...)"
+/// }
+/// draw(emu.framebuffer());
+/// ```
+#[wasm_bindgen]
+pub struct Emulator {
+    app: AppState,
+    rom: Vec<u8>,
+
+    /// Instructions executed by `run_frame`
+    pub cycles_per_frame: usize,
+}
+
+#[wasm_bindgen]
+impl Emulator {
+    /// Throws if the ROM is empty or doesn't fit into memory
+    #[wasm_bindgen(constructor)]
+    pub fn new(rom: &[u8]) -> Result<Emulator, JsValue> {
+        Ok(Self {
+            app: AppState::try_new(rom).map_err(js_error)?,
+            rom: rom.to_vec(),
+            cycles_per_frame: CYCLES_PER_FRAME,
+        })
+    }
+
+    /// Executes a single instruction without ticking the timers,
+    /// throws if it can't be executed
+    pub fn step(&mut self) -> Result<(), JsValue> {
+        self.app.try_step().map(|_| ()).map_err(|e| self.exec_error(e))
+    }
+
+    /// Executes one frame worth of instructions and ticks the timers,
+    /// has to be called at 60 Hz
+    ///
+    /// Throws at the first instruction that can't be executed, PC is left pointing at it.
+    pub fn run_frame(&mut self) -> Result<(), JsValue> {
+        self.app
+            .try_run_frame(self.cycles_per_frame, |_, _| {})
+            .map(|_| ())
+            .map_err(|e| self.exec_error(e))
+    }
+
+    pub fn key_down(&mut self, key: u8) {
+        self.app.set_key(key, true);
+    }
+
+    pub fn key_up(&mut self, key: u8) {
+        self.app.set_key(key, false);
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn width(&self) -> usize {
+        DISPLAY_WIDTH
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn height(&self) -> usize {
+        DISPLAY_HEIGHT
+    }
+
+    /// Returns the screen row by row with one byte per pixel, 1 is lit
+    pub fn framebuffer(&self) -> Vec<u8> {
+        self.app.vram.iter().flatten().map(|pixel| *pixel as u8).collect()
+    }
+
+    /// Returns V0 - VF
+    pub fn registers(&self) -> Vec<u8> {
+        self.app.registers().to_vec()
+    }
+
+    pub fn pc(&self) -> usize {
+        self.app.pc
+    }
+
+    pub fn index(&self) -> u16 {
+        self.app.I
+    }
+
+    pub fn sp(&self) -> usize {
+        self.app.sp
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.app.delay_timer
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.app.sound_timer
+    }
+
+    pub fn save_state(&self) -> Vec<u8> {
+        self.app.save_state().to_vec()
+    }
+
+    /// Throws if the data isn't a save state, the emulator is left untouched then
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), JsValue> {
+        self.app.load_state(state).map_err(js_error)
+    }
+
+    /// Restarts the ROM from the beginning
+    pub fn reset(&mut self) {
+        self.app = AppState::new(&self.rom);
+    }
+}
+
+impl Emulator {
+    /// Names the instruction that failed along with the error
+    fn exec_error(&self, e: ExecError) -> JsValue {
+        js_error(format_args!("{} at 0x{:03X} ({})", e, self.app.pc, self.app.current_op()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::IBM_LOGO;
+
+    use super::Emulator;
+
+    #[test]
+    fn run_and_restore() {
+        let mut emu = Emulator::new(IBM_LOGO).unwrap();
+        assert_eq!(0x200, emu.pc());
+        assert_eq!(64 * 32, emu.framebuffer().len());
+
+        emu.run_frame().unwrap();
+        let state = emu.save_state();
+        let frame = emu.framebuffer();
+        assert!(frame.contains(&1));
+
+        emu.run_frame().unwrap();
+        assert_ne!(frame, emu.framebuffer());
+
+        emu.load_state(&state).unwrap();
+        assert_eq!(frame, emu.framebuffer());
+
+        emu.reset();
+        assert_eq!(0x200, emu.pc());
+        assert!(!emu.framebuffer().contains(&1));
+    }
+}
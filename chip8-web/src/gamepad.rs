@@ -0,0 +1,120 @@
+use chip8::chip8::ch8_types::KEYPAD_SIZE;
+use wasm_bindgen::JsCast;
+use web_sys::{Gamepad, GamepadButton};
+
+use crate::dom::window;
+
+/// Number of buttons in the W3C "standard" gamepad layout
+pub const STANDARD_BUTTONS: usize = 17;
+
+/// Maps the buttons of the standard gamepad layout to hex keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GamepadMapping {
+    buttons: [Option<u8>; STANDARD_BUTTONS],
+}
+
+impl GamepadMapping {
+    // Button indices of https://w3c.github.io/gamepad/#remapping
+    pub const A: usize = 0;
+    pub const B: usize = 1;
+    pub const Y: usize = 3;
+    pub const UP: usize = 12;
+    pub const DOWN: usize = 13;
+    pub const LEFT: usize = 14;
+    pub const RIGHT: usize = 15;
+
+    /// The WASD + E layout most modern CHIP-8 games are written for
+    pub const DEFAULT: GamepadMapping = GamepadMapping::new()
+        .with(GamepadMapping::UP, 0x5)
+        .with(GamepadMapping::LEFT, 0x7)
+        .with(GamepadMapping::DOWN, 0x8)
+        .with(GamepadMapping::RIGHT, 0x9)
+        .with(GamepadMapping::A, 0x6)
+        .with(GamepadMapping::B, 0x4);
+
+    /// A mapping without any buttons
+    pub const fn new() -> Self {
+        Self {
+            buttons: [None; STANDARD_BUTTONS],
+        }
+    }
+
+    /// Maps the button to the hex key
+    pub const fn with(mut self, button: usize, key: u8) -> Self {
+        self.buttons[button] = Some(key & 0xF);
+        self
+    }
+
+    pub fn key_for(&self, button: usize) -> Option<u8> {
+        self.buttons.get(button).copied().flatten()
+    }
+}
+
+impl Default for GamepadMapping {
+    fn default() -> Self {
+        GamepadMapping::DEFAULT
+    }
+}
+
+/// Polls the connected gamepads and feeds their buttons into the keypad
+#[derive(Debug, Default)]
+pub struct GamepadInput {
+    pub mapping: GamepadMapping,
+
+    /// Keys currently held by a gamepad, only changes are reported
+    held: [bool; KEYPAD_SIZE],
+}
+
+impl GamepadInput {
+    /// Reads `navigator.getGamepads()` and reports the changed keys, has to be called every frame
+    pub fn poll(&mut self, mut set_key: impl FnMut(u8, bool)) {
+        let mut held = [false; KEYPAD_SIZE];
+
+        let pads = match window().navigator().get_gamepads() {
+            Ok(pads) => pads,
+            Err(_) => return,
+        };
+
+        for pad in pads.iter() {
+            let Ok(pad) = pad.dyn_into::<Gamepad>() else {
+                continue;
+            };
+            if !pad.connected() {
+                continue;
+            }
+
+            for (i, button) in pad.buttons().iter().enumerate() {
+                let pressed = button
+                    .dyn_into::<GamepadButton>()
+                    .map(|b| b.pressed())
+                    .unwrap_or(false);
+
+                if let (true, Some(key)) = (pressed, self.mapping.key_for(i)) {
+                    held[key as usize] = true;
+                }
+            }
+        }
+
+        for (key, (now, before)) in held.iter().zip(self.held.iter()).enumerate() {
+            if now != before {
+                set_key(key as u8, *now);
+            }
+        }
+        self.held = held;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GamepadMapping;
+
+    #[test]
+    fn mapping() {
+        let mapping = GamepadMapping::new().with(GamepadMapping::UP, 0x1);
+
+        assert_eq!(Some(0x1), mapping.key_for(GamepadMapping::UP));
+        assert_eq!(None, mapping.key_for(GamepadMapping::DOWN));
+        assert_eq!(None, mapping.key_for(99));
+        assert_eq!(Some(0x5), GamepadMapping::DEFAULT.key_for(GamepadMapping::UP));
+    }
+}
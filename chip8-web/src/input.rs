@@ -0,0 +1,255 @@
+use std::{cell::RefCell, rc::Rc};
+
+use chip8::chip8::ch8_types::KEYPAD_SIZE;
+use wasm_bindgen::prelude::*;
+use web_sys::{Event, KeyboardEvent};
+
+use crate::dom::{document, window, KeyHandler};
+
+/// Key of the `localStorage` entry holding the key table
+const STORAGE_KEY: &str = "chip8.keymap";
+
+/// `KeyboardEvent.code` of the keys mapped to 0x0 - 0xF
+///
+/// The hex keypad of the COSMAC VIP is laid out on the left side of a QWERTY keyboard:
+/// ```text
+/// 1 2 3 C      1 2 3 4
+/// 4 5 6 D  ->  Q W E R
+/// 7 8 9 E      A S D F
+/// A 0 B F      Z X C V
+/// ```
+pub const DEFAULT_KEYMAP: [&str; KEYPAD_SIZE] = [
+    "KeyX", "Digit1", "Digit2", "Digit3", "KeyQ", "KeyW", "KeyE", "KeyA", "KeyS", "KeyD", "KeyZ",
+    "KeyC", "Digit4", "KeyR", "KeyF", "KeyV",
+];
+
+/// Order of the hex keys on the keypad, row by row
+pub const KEYPAD_LAYOUT: [u8; KEYPAD_SIZE] = [
+    0x1, 0x2, 0x3, 0xC, 0x4, 0x5, 0x6, 0xD, 0x7, 0x8, 0x9, 0xE, 0xA, 0x0, 0xB, 0xF,
+];
+
+/// Maps keyboard keys to the keys of the hex keypad
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyMap {
+    codes: [String; KEYPAD_SIZE],
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        Self {
+            codes: DEFAULT_KEYMAP.map(String::from),
+        }
+    }
+}
+
+impl KeyMap {
+    /// Parses a comma separated list of 16 key codes
+    pub fn parse(data: &str) -> Option<KeyMap> {
+        let codes: Vec<&str> = data.split(',').collect();
+        if codes.len() != KEYPAD_SIZE || codes.iter().any(|c| c.is_empty()) {
+            return None;
+        }
+
+        let mut map = KeyMap::default();
+        for (code, c) in map.codes.iter_mut().zip(codes) {
+            *code = c.to_string();
+        }
+        Some(map)
+    }
+
+    pub fn serialize(&self) -> String {
+        self.codes.join(",")
+    }
+
+    /// Loads the table from `localStorage`, falls back to the default layout
+    pub fn load() -> KeyMap {
+        window()
+            .local_storage()
+            .ok()
+            .flatten()
+            .and_then(|s| s.get_item(STORAGE_KEY).ok().flatten())
+            .and_then(|data| KeyMap::parse(&data))
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) {
+        if let Ok(Some(storage)) = window().local_storage() {
+            let _ = storage.set_item(STORAGE_KEY, &self.serialize());
+        }
+    }
+
+    /// Returns the hex key mapped to the keyboard key
+    pub fn key_for(&self, code: &str) -> Option<u8> {
+        self.codes.iter().position(|c| c == code).map(|k| k as u8)
+    }
+
+    pub fn code_for(&self, key: u8) -> &str {
+        &self.codes[key as usize & 0xF]
+    }
+
+    /// Maps the keyboard key to the hex key, a previous mapping of the keyboard key is swapped
+    pub fn set(&mut self, key: u8, code: &str) {
+        let key = key as usize & 0xF;
+        if let Some(other) = self.codes.iter().position(|c| c == code) {
+            self.codes.swap(key, other);
+        }
+        self.codes[key] = code.to_string();
+    }
+}
+
+/// Devices that can hold keys of the hex keypad
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeySource {
+    Keyboard,
+    Touch,
+    Gamepad,
+}
+
+/// Tracks the keys held on every device, a key stays down while any of them holds it
+#[derive(Debug, Default)]
+pub struct KeySources {
+    held: [[bool; KEYPAD_SIZE]; 3],
+}
+
+impl KeySources {
+    /// Records the change of one device and returns if the key is still held on any
+    pub fn set(&mut self, source: KeySource, key: u8, pressed: bool) -> bool {
+        self.held[source as usize][key as usize & 0xF] = pressed;
+        self.held.iter().any(|held| held[key as usize & 0xF])
+    }
+
+    /// Wraps the handler for one device, it only sees the combined state of all of them
+    pub fn handler(sources: &Rc<RefCell<KeySources>>, source: KeySource, set_key: KeyHandler) -> KeyHandler {
+        let sources = sources.clone();
+        Rc::new(move |key, pressed| {
+            let down = sources.borrow_mut().set(source, key, pressed);
+            set_key(key, down);
+        })
+    }
+}
+
+struct Keyboard {
+    map: KeyMap,
+
+    /// Hex key waiting for the keyboard key it should be mapped to
+    remapping: Option<u8>,
+}
+
+/// Feeds `keydown`/`keyup` into the keypad of the emulator and builds the remapping
+/// table inside `#keymap`
+pub fn init_keyboard(set_key: KeyHandler) {
+    let keyboard = Rc::new(RefCell::new(Keyboard {
+        map: KeyMap::load(),
+        remapping: None,
+    }));
+
+    let table = document().get_element_by_id("keymap").expect("no keymap element");
+    let mut buttons = Vec::new();
+    for key in KEYPAD_LAYOUT {
+        let button = document().create_element("button").unwrap();
+        button.set_text_content(Some(&label(key, keyboard.borrow().map.code_for(key))));
+
+        let on_click = {
+            let (keyboard, button) = (keyboard.clone(), button.clone());
+            Closure::<dyn FnMut(Event)>::new(move |_: Event| {
+                keyboard.borrow_mut().remapping = Some(key);
+                button.set_text_content(Some(&format!("{:X}: press a key", key)));
+            })
+        };
+        button
+            .add_event_listener_with_callback("click", on_click.as_ref().unchecked_ref())
+            .unwrap();
+        on_click.forget();
+
+        table.append_child(&button).unwrap();
+        buttons.push(button);
+    }
+
+    let on_keydown = {
+        let (set_key, keyboard) = (set_key.clone(), keyboard.clone());
+        Closure::<dyn FnMut(KeyboardEvent)>::new(move |e: KeyboardEvent| {
+            let mut keyboard = keyboard.borrow_mut();
+
+            if let Some(key) = keyboard.remapping.take() {
+                e.prevent_default();
+                keyboard.map.set(key, &e.code());
+                keyboard.map.save();
+
+                for (k, button) in KEYPAD_LAYOUT.iter().zip(buttons.iter()) {
+                    button.set_text_content(Some(&label(*k, keyboard.map.code_for(*k))));
+                }
+                return;
+            }
+
+            if let Some(key) = keyboard.map.key_for(&e.code()) {
+                e.prevent_default();
+                set_key(key, true);
+            }
+        })
+    };
+
+    let on_keyup = Closure::<dyn FnMut(KeyboardEvent)>::new(move |e: KeyboardEvent| {
+        if let Some(key) = keyboard.borrow().map.key_for(&e.code()) {
+            e.prevent_default();
+            set_key(key, false);
+        }
+    });
+
+    document()
+        .add_event_listener_with_callback("keydown", on_keydown.as_ref().unchecked_ref())
+        .unwrap();
+    document()
+        .add_event_listener_with_callback("keyup", on_keyup.as_ref().unchecked_ref())
+        .unwrap();
+
+    on_keydown.forget();
+    on_keyup.forget();
+}
+
+fn label(key: u8, code: &str) -> String {
+    format!("{:X}: {}", key, code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{KeyMap, KeySource, KeySources};
+
+    #[test]
+    fn default_layout() {
+        let map = KeyMap::default();
+        assert_eq!(Some(0x1), map.key_for("Digit1"));
+        assert_eq!(Some(0xC), map.key_for("Digit4"));
+        assert_eq!(Some(0x0), map.key_for("KeyX"));
+        assert_eq!(None, map.key_for("KeyP"));
+    }
+
+    #[test]
+    fn sources_combine() {
+        let mut sources = KeySources::default();
+        assert!(sources.set(KeySource::Keyboard, 0x5, true));
+        assert!(sources.set(KeySource::Gamepad, 0x5, true));
+
+        // Releasing the button keeps the key held on the keyboard down
+        assert!(sources.set(KeySource::Gamepad, 0x5, false));
+        assert!(!sources.set(KeySource::Keyboard, 0x5, false));
+        assert!(!sources.set(KeySource::Touch, 0x6, false));
+    }
+
+    #[test]
+    fn remap_swaps() {
+        let mut map = KeyMap::default();
+        map.set(0x5, "KeyQ");
+
+        assert_eq!(Some(0x5), map.key_for("KeyQ"));
+        assert_eq!(Some(0x4), map.key_for("KeyW"));
+    }
+
+    #[test]
+    fn parse_round_trip() {
+        let mut map = KeyMap::default();
+        map.set(0x0, "ArrowUp");
+
+        assert_eq!(Some(map.clone()), KeyMap::parse(&map.serialize()));
+        assert_eq!(None, KeyMap::parse("KeyX,KeyY"));
+    }
+}
@@ -1,34 +1,215 @@
+mod audio;
+mod controls;
+mod debugger;
 mod dom;
+mod emulator;
+mod gamepad;
+mod input;
+mod loader;
+mod rom;
+mod saves;
+mod timing;
+mod touch;
 mod utils;
+mod worker;
 
 use core::str;
-
-use chip8::{app::AppState, chip8::ch8_types::DISPLAY_WIDTH, display::DisplayController};
-use dom::{update_canvas, window};
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
+
+use audio::{init_audio, Beeper};
+use chip8::{
+    chip8::ch8_types::DISPLAY_WIDTH,
+    debug::FrameEnd,
+    display::DisplayController,
+    phosphor::{Phosphor, PhosphorMode},
+    render::{intensity_from_vram, Filter, Palette},
+};
+use controls::{init_controls, StatusBar};
+use debugger::DebuggerPanel;
+use dom::{
+    element, init_filter_select, init_palette_select, init_phosphor_select, request_animation_frame, FrameCallback,
+    KeyHandler, Screen,
+};
+use gamepad::GamepadInput;
+use input::{init_keyboard, KeySource, KeySources};
+use loader::{init_loader, rom_from_url, RomLoader};
+use saves::SaveSlots;
+use timing::{init_speed_controls, FrameClock, Speed};
+use touch::init_touchpad;
+use utils::js_error;
 use wasm_bindgen::prelude::*;
 use web_sys::console;
 
-pub const IBM_LOGO: &[u8] = include_bytes!("../../chip8-roms/roms/IBM Logo.ch8");
+pub use emulator::Emulator;
+pub use rom::IBM_LOGO;
+pub use worker::{run_worker, start_in_worker};
+
+/// Default number of instructions executed per frame, the frames run at 60 Hz
+/// to keep the timers in time
+const CYCLES_PER_FRAME: usize = 10;
+
+thread_local! {
+    /// Set by `start`, lets `load_rom` reach the running emulator
+    static LOADER: RefCell<Option<RomLoader>> = const { RefCell::new(None) };
+}
+
+/// Restarts the emulator with the given program
+#[wasm_bindgen]
+pub fn load_rom(bytes: &[u8]) -> Result<(), JsValue> {
+    LOADER.with(|loader| match loader.borrow().as_ref() {
+        Some(loader) => loader.load(bytes, None).map_err(js_error),
+        None => Err(JsValue::from_str("the emulator is not running")),
+    })
+}
 
-#[wasm_bindgen(start)]
-fn run() {
-    let mut rt = AppState::new(IBM_LOGO);
+/// Returns the ROM to boot with and its file name, shared links boot straight into their ROM
+fn initial_rom() -> (Vec<u8>, Option<String>) {
+    rom_from_url().unwrap_or_else(|| (IBM_LOGO.to_vec(), Some("IBM Logo".to_string())))
+}
 
-    let tick = Closure::<dyn FnMut()>::new(move || {
-        let inst = rt.step();
-        update_canvas(&rt.vram);
+/// Runs the emulator on the page, needs the elements of `index.html`
+#[wasm_bindgen]
+pub fn start() {
+    let loader = RomLoader::new(IBM_LOGO);
+    let (rom, name) = initial_rom();
+    if let Err(e) = loader.load(&rom, name.as_deref()) {
+        console::error_1(&JsValue::from_str(&format!("could not load the ROM from the URL: {}", e)));
+    }
+    init_loader(loader.clone());
+    LOADER.with(|l| *l.borrow_mut() = Some(loader.clone()));
 
-        let dbg_str = format!("[DEBUG] OP: {:?}, PC: {}, I: {}, SP: {}", inst, rt.pc, rt.I, rt.sp);
-        
-        #[cfg(debug_assertions)]
-        console::log_1(&JsValue::from_str(&dbg_str));
-    });
+    let app = loader.app.clone();
+    let set_key: KeyHandler = {
+        let app = app.clone();
+        Rc::new(move |key, pressed| app.borrow_mut().set_key(key, pressed))
+    };
+    let sources = Rc::new(RefCell::new(KeySources::default()));
+    init_keyboard(KeySources::handler(&sources, KeySource::Keyboard, set_key.clone()));
+    init_touchpad(KeySources::handler(&sources, KeySource::Touch, set_key));
+
+    // A palette change has to repaint the whole canvas
+    let palette = Rc::new(Cell::new(Palette::default()));
+    let repaint = Rc::new(Cell::new(false));
+    {
+        let (palette, repaint) = (palette.clone(), repaint.clone());
+        init_palette_select(move |p| {
+            palette.set(p);
+            repaint.set(true);
+        });
+    }
 
-    window()
-        .set_interval_with_callback_and_timeout_and_arguments_0(tick.as_ref().unchecked_ref(), 100)
-        .expect("error");
+    let filter = Rc::new(Cell::new(Filter::Nearest(1)));
+    {
+        let (filter, repaint) = (filter.clone(), repaint.clone());
+        init_filter_select(move |f| {
+            filter.set(f);
+            repaint.set(true);
+        });
+    }
 
-    tick.forget();
+    let phosphor_mode = Rc::new(Cell::new(PhosphorMode::Off));
+    {
+        let (phosphor_mode, repaint) = (phosphor_mode.clone(), repaint.clone());
+        init_phosphor_select(move |mode| {
+            phosphor_mode.set(mode);
+            repaint.set(true);
+        });
+    }
+    let mut phosphor = Phosphor::default();
+
+    let beeper = Rc::new(RefCell::new(Beeper::default()));
+    init_audio(beeper.clone());
+
+    let mut gamepad = GamepadInput::default();
+    let mapping = loader.gamepad.clone();
+
+    let speed = Rc::new(Cell::new(Speed::default()));
+    init_speed_controls(speed.clone());
+    let mut clock = FrameClock::default();
+
+    let saves = SaveSlots::new(loader.clone());
+
+    let paused = Rc::new(Cell::new(false));
+    let mut status = StatusBar::new();
+    let panel = DebuggerPanel::new();
+    init_controls(
+        loader,
+        paused.clone(),
+        speed.clone(),
+        panel.debugger.clone(),
+        status.error.clone(),
+    );
+    let mut screen = Screen::new(element("canvas").dyn_into().unwrap());
+
+    // Every animation frame schedules the next one with the same closure
+    let frame: Rc<RefCell<Option<FrameCallback>>> = Rc::new(RefCell::new(None));
+    let next = frame.clone();
+    *frame.borrow_mut() = Some(Closure::new(move |now: f64| {
+        {
+            let mut rt = app.borrow_mut();
+            gamepad.mapping = mapping.get();
+            gamepad.poll(|key, pressed| {
+                let down = sources.borrow_mut().set(KeySource::Gamepad, key, pressed);
+                rt.set_key(key, down);
+            });
+
+            // The clock keeps running while paused so resuming doesn't catch up
+            let speed = speed.get();
+            let mut frames = speed.frames(clock.advance(now));
+            let mut executed = 0;
+            let mut emulated = false;
+            phosphor.mode = phosphor_mode.get();
+            while frames > 0 && !paused.get() {
+                match panel.debugger.borrow().run_frame(&mut rt, speed.cycles_per_frame) {
+                    Ok(FrameEnd::Completed(n)) => executed += n,
+                    Ok(FrameEnd::Breakpoint(n)) => {
+                        executed += n;
+                        paused.set(true);
+                    }
+                    Err(e) => {
+                        paused.set(true);
+                        status.error.replace(Some(e));
+                    }
+                }
+                phosphor.update(&rt.vram);
+                emulated = true;
+                frames -= 1;
+            }
+            status.ips.add(now, executed);
+            status.update(&rt, paused.get());
+            panel.update(&rt);
+            saves.update();
+            // The timers stand still while paused, so does the beep
+            beeper
+                .borrow_mut()
+                .update((rt.is_sounding() && !paused.get()) as u8);
+
+            // Frames are cheap to present in full, the dirty rect only tells if anything changed.
+            // Fading pixels change without touching the VRAM, single steps while paused change
+            // the VRAM outside of emulated frames.
+            let dirty = rt.take_dirty().is_some();
+            if dirty && !emulated {
+                phosphor.update(&rt.vram);
+            }
+            let fading = emulated && phosphor.mode != PhosphorMode::Off;
+            if dirty || repaint.replace(false) || fading {
+                match (phosphor.mode, filter.get()) {
+                    (PhosphorMode::Off, Filter::Nearest(1)) => screen.present_vram(&rt.vram, &palette.get()),
+                    (PhosphorMode::Off, filter) => {
+                        screen.present_frame(&intensity_from_vram(&rt.vram), filter, &palette.get())
+                    }
+                    (_, filter) => screen.present_frame(phosphor.frame(), filter, &palette.get()),
+                }
+            }
+        }
+
+        request_animation_frame(next.borrow().as_ref().unwrap());
+    }));
+
+    request_animation_frame(frame.borrow().as_ref().unwrap());
 }
 
 pub fn row_to_string(o: &[bool; DISPLAY_WIDTH]) -> String {
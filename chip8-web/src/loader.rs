@@ -0,0 +1,222 @@
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
+
+use chip8::app::{fnv1a, AppState, RomError};
+use wasm_bindgen::prelude::*;
+use web_sys::{DragEvent, Event, File, HtmlInputElement, UrlSearchParams};
+
+use crate::{
+    dom::{document, read_file, window},
+    gamepad::GamepadMapping,
+    rom::rom_info,
+};
+
+/// Largest ROM that still gets a share link, keeps the URL at a few kilobytes
+const SHARE_LIMIT: usize = 2048;
+
+/// Replaces the running program, shared by the file picker, drag-and-drop and `load_rom`
+#[derive(Clone)]
+pub struct RomLoader {
+    pub app: Rc<RefCell<AppState>>,
+    pub gamepad: Rc<Cell<GamepadMapping>>,
+
+    /// The running program as it was loaded, for resets
+    rom: Rc<RefCell<Vec<u8>>>,
+    rom_hash: Rc<Cell<u64>>,
+}
+
+impl RomLoader {
+    /// Starts the emulator with the ROM
+    pub fn new(rom: &[u8]) -> Self {
+        Self {
+            app: Rc::new(RefCell::new(AppState::new(rom))),
+            gamepad: Rc::new(Cell::new(GamepadMapping::default())),
+            rom: Rc::new(RefCell::new(rom.to_vec())),
+            rom_hash: Rc::new(Cell::new(fnv1a(rom))),
+        }
+    }
+
+    /// Restarts the emulator with the ROM, `name` is the file name used to look up
+    /// the gamepad mapping and the share link
+    pub fn load(&self, rom: &[u8], name: Option<&str>) -> Result<(), RomError> {
+        *self.app.borrow_mut() = AppState::try_new(rom)?;
+        *self.rom.borrow_mut() = rom.to_vec();
+        self.rom_hash.set(fnv1a(rom));
+        self.gamepad.set(
+            name.and_then(rom_info)
+                .map(|info| info.gamepad)
+                .unwrap_or_default(),
+        );
+
+        update_share_link(rom, name);
+        set_status("");
+        Ok(())
+    }
+
+    /// Identifies the running program, e.g. for its save slots
+    pub fn rom_hash(&self) -> u64 {
+        self.rom_hash.get()
+    }
+
+    /// Restarts the running program from the beginning
+    pub fn reset(&self) {
+        *self.app.borrow_mut() = AppState::new(&self.rom.borrow());
+    }
+
+    fn load_or_report(&self, rom: &[u8], name: Option<&str>) {
+        if let Err(e) = self.load(rom, name) {
+            set_status(&format!("Could not load {}: {}", name.unwrap_or("ROM"), e));
+        }
+    }
+
+    /// Reads the file asynchronously and loads it once it's read
+    fn load_file(&self, file: File) {
+        let (loader, name) = (self.clone(), file.name());
+        if read_file(&file, move |rom| loader.load_or_report(&rom, Some(&name))).is_err() {
+            set_status("Could not read the file");
+        }
+    }
+}
+
+/// Reads the ROM from the `?rom=` parameter of the page URL, `?name=` is optional
+pub fn rom_from_url() -> Option<(Vec<u8>, Option<String>)> {
+    let search = window().location().search().ok()?;
+    let params = UrlSearchParams::new_with_str(&search).ok()?;
+
+    let rom = decode_base64(&params.get("rom")?)?;
+    Some((rom, params.get("name")))
+}
+
+/// Hooks up the `#rom-file` picker and dropping files onto the canvas
+pub fn init_loader(loader: RomLoader) {
+    let picker = document()
+        .get_element_by_id("rom-file")
+        .expect("no rom-file element")
+        .dyn_into::<HtmlInputElement>()
+        .unwrap();
+
+    let on_pick = {
+        let (loader, picker) = (loader.clone(), picker.clone());
+        Closure::<dyn FnMut(Event)>::new(move |_: Event| {
+            if let Some(file) = picker.files().and_then(|files| files.get(0)) {
+                loader.load_file(file);
+            }
+        })
+    };
+    picker
+        .add_event_listener_with_callback("change", on_pick.as_ref().unchecked_ref())
+        .unwrap();
+    on_pick.forget();
+
+    let canvas = document().get_element_by_id("canvas").expect("no canvas element");
+
+    // The browser only allows dropping if `dragover` is cancelled
+    let on_dragover = Closure::<dyn FnMut(DragEvent)>::new(|e: DragEvent| e.prevent_default());
+    let on_drop = Closure::<dyn FnMut(DragEvent)>::new(move |e: DragEvent| {
+        e.prevent_default();
+        let file = e
+            .data_transfer()
+            .and_then(|data| data.files())
+            .and_then(|files| files.get(0));
+        if let Some(file) = file {
+            loader.load_file(file);
+        }
+    });
+    canvas
+        .add_event_listener_with_callback("dragover", on_dragover.as_ref().unchecked_ref())
+        .unwrap();
+    canvas
+        .add_event_listener_with_callback("drop", on_drop.as_ref().unchecked_ref())
+        .unwrap();
+    on_dragover.forget();
+    on_drop.forget();
+}
+
+/// Points `#share` at a URL that boots the ROM, if it's small enough
+fn update_share_link(rom: &[u8], name: Option<&str>) {
+    let Some(link) = document().get_element_by_id("share") else {
+        return;
+    };
+    if rom.len() > SHARE_LIMIT {
+        let _ = link.remove_attribute("href");
+        return;
+    }
+
+    let Ok(params) = UrlSearchParams::new() else {
+        return;
+    };
+    params.append("rom", &encode_base64(rom));
+    if let Some(name) = name {
+        params.append("name", name);
+    }
+    let _ = link.set_attribute("href", &format!("?{}", String::from(params.to_string())));
+}
+
+fn set_status(text: &str) {
+    if let Some(status) = document().get_element_by_id("rom-status") {
+        status.set_text_content(Some(text));
+    }
+}
+
+const BASE64_URL: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+/// Encodes with the URL-safe alphabet and without padding
+pub fn encode_base64(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+
+    for chunk in data.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |acc, (i, b)| acc | ((*b as u32) << (16 - 8 * i)));
+        // n bytes produce n + 1 characters
+        for i in 0..=chunk.len() {
+            out.push(BASE64_URL[((bits >> (18 - 6 * i)) & 0x3F) as usize] as char);
+        }
+    }
+
+    out
+}
+
+/// Decodes standard and URL-safe base64, the padding is optional
+pub fn decode_base64(data: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(data.len() * 3 / 4);
+    let mut bits = 0u32;
+    let mut count = 0;
+
+    for c in data.trim_end_matches('=').bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            _ => return None,
+        };
+
+        bits = (bits << 6) | value as u32;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            out.push((bits >> count) as u8);
+        }
+    }
+
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_base64, encode_base64};
+
+    #[test]
+    fn base64_round_trip() {
+        let data = [0x00, 0xE0, 0xA2, 0x2A, 0xFB, 0xFF, 0x12];
+        for len in 0..data.len() {
+            assert_eq!(Some(data[..len].to_vec()), decode_base64(&encode_base64(&data[..len])));
+        }
+
+        assert_eq!("AOCiKvv_", encode_base64(&data[..6]));
+        assert_eq!(Some(vec![0xFB, 0xFF]), decode_base64("+/8="));
+        assert_eq!(None, decode_base64("AO?i"));
+    }
+}
@@ -0,0 +1,79 @@
+use crate::gamepad::GamepadMapping;
+
+pub const IBM_LOGO: &[u8] = include_bytes!("../../chip8-roms/roms/IBM Logo.ch8");
+
+/// Metadata of a ROM known to the web frontend
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RomInfo {
+    /// Title of the ROM, matches the file name without extension
+    pub title: &'static str,
+
+    /// Which gamepad buttons press which hex keys
+    pub gamepad: GamepadMapping,
+}
+
+/// ROMs that need more than the default gamepad mapping
+pub const KNOWN_ROMS: [RomInfo; 5] = [
+    RomInfo {
+        title: "IBM Logo",
+        gamepad: GamepadMapping::DEFAULT,
+    },
+    // Left paddle on 1/4, right paddle on C/D
+    RomInfo {
+        title: "Pong",
+        gamepad: GamepadMapping::new()
+            .with(GamepadMapping::UP, 0x1)
+            .with(GamepadMapping::DOWN, 0x4)
+            .with(GamepadMapping::Y, 0xC)
+            .with(GamepadMapping::A, 0xD),
+    },
+    RomInfo {
+        title: "Space Invaders",
+        gamepad: GamepadMapping::new()
+            .with(GamepadMapping::LEFT, 0x4)
+            .with(GamepadMapping::RIGHT, 0x6)
+            .with(GamepadMapping::A, 0x5),
+    },
+    RomInfo {
+        title: "Tetris",
+        gamepad: GamepadMapping::new()
+            .with(GamepadMapping::LEFT, 0x5)
+            .with(GamepadMapping::RIGHT, 0x6)
+            .with(GamepadMapping::DOWN, 0x7)
+            .with(GamepadMapping::A, 0x4),
+    },
+    RomInfo {
+        title: "Breakout",
+        gamepad: GamepadMapping::new()
+            .with(GamepadMapping::LEFT, 0x4)
+            .with(GamepadMapping::RIGHT, 0x6),
+    },
+];
+
+/// Looks up the metadata of a ROM by title or file name, case insensitive
+///
+/// Author suffixes like in `Pong [Paul Vervalin, 1990].ch8` are ignored.
+pub fn rom_info(name: &str) -> Option<&'static RomInfo> {
+    let file = name.rsplit('/').next().unwrap_or(name);
+    let title = file
+        .trim_end_matches(".ch8")
+        .split(" [")
+        .next()
+        .unwrap_or(file);
+
+    KNOWN_ROMS
+        .iter()
+        .find(|info| info.title.eq_ignore_ascii_case(title))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::rom_info;
+
+    #[test]
+    fn test_rom_info() {
+        assert_eq!("Pong", rom_info("roms/PONG.ch8").unwrap().title);
+        assert_eq!("Tetris", rom_info("Tetris [Fran Dachille, 1991].ch8").unwrap().title);
+        assert_eq!(None, rom_info("unknown.ch8"));
+    }
+}
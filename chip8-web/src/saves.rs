@@ -0,0 +1,307 @@
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
+
+use chip8::{app::AppState, render::Palette};
+use wasm_bindgen::prelude::*;
+use web_sys::{
+    js_sys::{Array, Uint8Array},
+    Blob, Element, Event, HtmlAnchorElement, HtmlCanvasElement, HtmlInputElement, IdbDatabase, IdbRequest,
+    IdbTransactionMode, KeyboardEvent, Url,
+};
+
+use crate::{
+    dom::{document, element, on_click, read_file, window, Screen},
+    loader::RomLoader,
+};
+
+/// Save slots per ROM, slot 0 is used by the quick save hotkeys
+const SLOTS: usize = 4;
+
+const DB_NAME: &str = "chip8";
+const DB_VERSION: u32 = 1;
+const STORE: &str = "slots";
+
+const QUICK_SAVE_KEY: &str = "F5";
+const QUICK_LOAD_KEY: &str = "F9";
+
+/// Key of a slot in the object store, e.g. `00c0ffee00c0ffee/2`
+fn slot_key(rom_hash: u64, slot: usize) -> String {
+    format!("{:016x}/{}", rom_hash, slot)
+}
+
+/// Returns the state if it can be loaded, by restoring it into a scratch emulator
+fn parse_state(data: &[u8]) -> Option<AppState> {
+    let mut app = AppState::new(&[]);
+    app.load_state(data).ok()?;
+    Some(app)
+}
+
+/// The save slots inside `#slots`, stored in IndexedDB under the hash of the running ROM
+#[derive(Clone)]
+pub struct SaveSlots {
+    loader: RomLoader,
+    db: Rc<RefCell<Option<IdbDatabase>>>,
+    thumbnails: Rc<RefCell<Vec<Screen>>>,
+
+    /// ROM the thumbnails were drawn for
+    shown: Rc<Cell<Option<u64>>>,
+}
+
+impl SaveSlots {
+    /// Opens the database and builds the slot controls, `F5` saves and `F9` loads slot 0
+    pub fn new(loader: RomLoader) -> Self {
+        let slots = SaveSlots {
+            loader,
+            db: Rc::new(RefCell::new(None)),
+            thumbnails: Rc::new(RefCell::new(Vec::new())),
+            shown: Rc::new(Cell::new(None)),
+        };
+
+        let container = element("slots");
+        for slot in 0..SLOTS {
+            container.append_child(&slots.build_slot(slot)).unwrap();
+        }
+        slots.init_hotkeys();
+
+        if let Err(e) = slots.open_db() {
+            web_sys::console::error_2(&JsValue::from_str("save slots are not available:"), &e);
+        }
+        slots
+    }
+
+    /// Redraws the thumbnails after a different ROM was loaded, has to be called every frame
+    pub fn update(&self) {
+        let hash = self.loader.rom_hash();
+        if self.db.borrow().is_some() && self.shown.replace(Some(hash)) != Some(hash) {
+            for slot in 0..SLOTS {
+                self.refresh(slot);
+            }
+        }
+    }
+
+    fn open_db(&self) -> Result<(), JsValue> {
+        let factory = window().indexed_db()?.ok_or("no IndexedDB")?;
+        let request = factory.open_with_u32(DB_NAME, DB_VERSION)?;
+
+        let on_upgrade = {
+            let request = request.clone();
+            Closure::once(move |_: Event| {
+                if let Ok(db) = request.result() {
+                    let _ = db.unchecked_into::<IdbDatabase>().create_object_store(STORE);
+                }
+            })
+        };
+        request.set_onupgradeneeded(Some(on_upgrade.as_ref().unchecked_ref()));
+        on_upgrade.forget();
+
+        let slots = self.clone();
+        on_success(&request, move |db| {
+            *slots.db.borrow_mut() = Some(db.unchecked_into());
+        });
+        Ok(())
+    }
+
+    fn store(&self, key: &str, data: &[u8]) -> Result<(), JsValue> {
+        let db = self.db.borrow();
+        let db = db.as_ref().ok_or("the database is not open yet")?;
+
+        let store = db
+            .transaction_with_str_and_mode(STORE, IdbTransactionMode::Readwrite)?
+            .object_store(STORE)?;
+        store.put_with_key(&Uint8Array::from(data), &JsValue::from_str(key))?;
+        Ok(())
+    }
+
+    /// Calls `on_load` with the content of the slot, if it has one
+    fn fetch(&self, slot: usize, on_load: impl FnOnce(Vec<u8>) + 'static) -> Result<(), JsValue> {
+        let db = self.db.borrow();
+        let db = db.as_ref().ok_or("the database is not open yet")?;
+
+        let key = slot_key(self.loader.rom_hash(), slot);
+        let request = db.transaction_with_str(STORE)?.object_store(STORE)?.get(&JsValue::from_str(&key))?;
+        on_success(&request, move |value| {
+            if !value.is_undefined() {
+                on_load(Uint8Array::new(&value).to_vec());
+            }
+        });
+        Ok(())
+    }
+
+    pub fn save(&self, slot: usize) {
+        let state = self.loader.app.borrow().save_state();
+        self.write(slot, &state);
+    }
+
+    fn write(&self, slot: usize, state: &[u8]) {
+        match self.store(&slot_key(self.loader.rom_hash(), slot), state) {
+            Ok(()) => self.draw_thumbnail(slot, parse_state(state).as_ref()),
+            Err(e) => web_sys::console::error_2(&JsValue::from_str("could not save:"), &e),
+        }
+    }
+
+    pub fn load(&self, slot: usize) {
+        let app = self.loader.app.clone();
+        let result = self.fetch(slot, move |state| {
+            if let Err(e) = app.borrow_mut().load_state(&state) {
+                web_sys::console::error_1(&JsValue::from_str(&format!("could not load slot {}: {}", slot, e)));
+            }
+        });
+        if let Err(e) = result {
+            web_sys::console::error_2(&JsValue::from_str("could not load:"), &e);
+        }
+    }
+
+    /// Offers the slot as a `.c8s` download
+    fn export(&self, slot: usize) {
+        let name = format!("{:016x}-slot{}.c8s", self.loader.rom_hash(), slot);
+        let _ = self.fetch(slot, move |state| {
+            let parts = Array::of1(&Uint8Array::from(&state[..]));
+            let Ok(url) = Blob::new_with_u8_array_sequence(&parts).and_then(|blob| Url::create_object_url_with_blob(&blob))
+            else {
+                return;
+            };
+
+            let link = document().create_element("a").unwrap().unchecked_into::<HtmlAnchorElement>();
+            link.set_href(&url);
+            link.set_download(&name);
+            link.click();
+
+            // Some browsers only start the download after the click returned
+            let revoke = Closure::once(move || {
+                let _ = Url::revoke_object_url(&url);
+            });
+            let _ = window().set_timeout_with_callback_and_timeout_and_arguments_0(revoke.as_ref().unchecked_ref(), 1000);
+            revoke.forget();
+        });
+    }
+
+    fn refresh(&self, slot: usize) {
+        self.draw_thumbnail(slot, None);
+
+        let slots = self.clone();
+        let _ = self.fetch(slot, move |state| slots.draw_thumbnail(slot, parse_state(&state).as_ref()));
+    }
+
+    /// Draws the screen of the saved state, an empty slot stays blank
+    fn draw_thumbnail(&self, slot: usize, app: Option<&AppState>) {
+        let blank = AppState::new(&[]);
+        let app = app.unwrap_or(&blank);
+
+        if let Some(screen) = self.thumbnails.borrow_mut().get_mut(slot) {
+            screen.present_vram(&app.vram, &Palette::default());
+        }
+    }
+
+    fn build_slot(&self, slot: usize) -> Element {
+        let container = document().create_element("div").unwrap();
+
+        let canvas = document().create_element("canvas").unwrap();
+        container.append_child(&canvas).unwrap();
+        self.thumbnails
+            .borrow_mut()
+            .push(Screen::new(canvas.unchecked_into::<HtmlCanvasElement>()));
+
+        let import = document()
+            .create_element("input")
+            .unwrap()
+            .unchecked_into::<HtmlInputElement>();
+        import.set_type("file");
+        import.set_accept(".c8s");
+        import.set_hidden(true);
+        let on_import = {
+            let (slots, import) = (self.clone(), import.clone());
+            Closure::<dyn FnMut(Event)>::new(move |_: Event| {
+                let Some(file) = import.files().and_then(|files| files.get(0)) else {
+                    return;
+                };
+                let slots = slots.clone();
+                let _ = read_file(&file, move |state| match parse_state(&state) {
+                    Some(_) => slots.write(slot, &state),
+                    None => web_sys::console::error_1(&JsValue::from_str("not a save state")),
+                });
+                import.set_value("");
+            })
+        };
+        import
+            .add_event_listener_with_callback("change", on_import.as_ref().unchecked_ref())
+            .unwrap();
+        on_import.forget();
+        container.append_child(&import).unwrap();
+
+        let label = if slot == 0 { "Quick".to_string() } else { format!("Slot {}", slot) };
+        let title = document().create_element("span").unwrap();
+        title.set_text_content(Some(&label));
+        container.append_child(&title).unwrap();
+
+        let slots = self.clone();
+        add_button(&container, "Save", move || slots.save(slot));
+        let slots = self.clone();
+        add_button(&container, "Load", move || slots.load(slot));
+        let slots = self.clone();
+        add_button(&container, "Export", move || slots.export(slot));
+        add_button(&container, "Import", move || import.click());
+
+        container
+    }
+
+    fn init_hotkeys(&self) {
+        let slots = self.clone();
+        let on_keydown = Closure::<dyn FnMut(KeyboardEvent)>::new(move |e: KeyboardEvent| match e.key().as_str() {
+            QUICK_SAVE_KEY => {
+                e.prevent_default();
+                slots.save(0);
+            }
+            QUICK_LOAD_KEY => {
+                e.prevent_default();
+                slots.load(0);
+            }
+            _ => {}
+        });
+        document()
+            .add_event_listener_with_callback("keydown", on_keydown.as_ref().unchecked_ref())
+            .unwrap();
+        on_keydown.forget();
+    }
+}
+
+fn add_button(parent: &Element, text: &str, callback: impl FnMut() + 'static) {
+    let button = document().create_element("button").unwrap();
+    button.set_text_content(Some(text));
+    on_click(&button, callback);
+    parent.append_child(&button).unwrap();
+}
+
+/// Calls `callback` with the result of the request once it succeeded
+fn on_success(request: &IdbRequest, callback: impl FnOnce(JsValue) + 'static) {
+    let listener = {
+        let request = request.clone();
+        Closure::once(move |_: Event| {
+            if let Ok(result) = request.result() {
+                callback(result);
+            }
+        })
+    };
+    request.set_onsuccess(Some(listener.as_ref().unchecked_ref()));
+    listener.forget();
+}
+
+#[cfg(test)]
+mod tests {
+    use chip8::app::AppState;
+
+    use super::{parse_state, slot_key};
+
+    #[test]
+    fn slot_keys() {
+        assert_eq!("00000000c0ffee00/2", slot_key(0xC0FFEE00, 2));
+    }
+
+    #[test]
+    fn import_validation() {
+        let state = AppState::new(crate::IBM_LOGO).save_state();
+        assert!(parse_state(&state).is_some());
+        assert!(parse_state(&state[..100]).is_none());
+    }
+}
@@ -0,0 +1,195 @@
+use std::{cell::Cell, rc::Rc};
+
+use wasm_bindgen::prelude::*;
+use web_sys::{Event, HtmlInputElement, HtmlSelectElement};
+
+use crate::{dom::document, CYCLES_PER_FRAME};
+
+/// Length of one emulated frame, the timers tick once per frame
+pub const TICK_MILLIS: f64 = 1000.0 / 60.0;
+
+/// Most frames run for one animation frame, the rest of a longer pause (e.g. a
+/// hidden tab) is dropped instead of fast-forwarding through it
+const MAX_TICKS_PER_FRAME: u32 = 4;
+
+/// Frames emulated per tick while turbo is on
+pub const TURBO_FACTOR: u32 = 8;
+
+/// Instructions per frame selectable in `#speed`
+pub const SPEED_PRESETS: [(&str, usize); 6] = [
+    ("Slow (7)", 7),
+    ("Normal (10)", CYCLES_PER_FRAME),
+    ("SUPER-CHIP (15)", 15),
+    ("Fast (30)", 30),
+    ("Very fast (100)", 100),
+    ("Maximum (1000)", 1000),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Speed {
+    /// Instructions executed per frame
+    pub cycles_per_frame: usize,
+
+    /// Runs [TURBO_FACTOR] frames per tick, timers included
+    pub turbo: bool,
+}
+
+impl Default for Speed {
+    fn default() -> Self {
+        Self {
+            cycles_per_frame: CYCLES_PER_FRAME,
+            turbo: false,
+        }
+    }
+}
+
+impl Speed {
+    /// Number of frames to emulate for the given number of ticks
+    pub fn frames(&self, ticks: u32) -> u32 {
+        if self.turbo {
+            ticks * TURBO_FACTOR
+        } else {
+            ticks
+        }
+    }
+}
+
+/// Converts the timestamps of `requestAnimationFrame` into 60 Hz ticks,
+/// independent of the refresh rate of the display
+#[derive(Debug, Default)]
+pub struct FrameClock {
+    last: Option<f64>,
+    accumulator: f64,
+}
+
+impl FrameClock {
+    /// Returns how many ticks passed since the last call
+    pub fn advance(&mut self, now: f64) -> u32 {
+        let elapsed = match self.last.replace(now) {
+            Some(last) => (now - last).max(0.0),
+            None => return 0,
+        };
+
+        self.accumulator += elapsed;
+        let ticks = (self.accumulator / TICK_MILLIS) as u32;
+        if ticks > MAX_TICKS_PER_FRAME {
+            self.accumulator = 0.0;
+            return MAX_TICKS_PER_FRAME;
+        }
+
+        self.accumulator -= ticks as f64 * TICK_MILLIS;
+        ticks
+    }
+}
+
+/// Measures the executed instructions per second over one second windows
+#[derive(Debug, Default)]
+pub struct IpsCounter {
+    window_start: Option<f64>,
+    count: usize,
+
+    /// Result of the last complete window
+    pub ips: usize,
+}
+
+impl IpsCounter {
+    /// Counts the instructions executed up to `now` in milliseconds
+    pub fn add(&mut self, now: f64, executed: usize) {
+        // The instructions ran in the time before `now`, the first call only starts the clock
+        let Some(start) = self.window_start else {
+            self.window_start = Some(now);
+            return;
+        };
+        self.count += executed;
+
+        if now - start >= 1000.0 {
+            self.ips = (self.count as f64 * 1000.0 / (now - start)).round() as usize;
+            self.count = 0;
+            self.window_start = Some(now);
+        }
+    }
+}
+
+/// Fills the `#speed` select with the presets and hooks up the `#turbo` checkbox
+pub fn init_speed_controls(speed: Rc<Cell<Speed>>) {
+    let select = document()
+        .get_element_by_id("speed")
+        .expect("no speed element")
+        .dyn_into::<HtmlSelectElement>()
+        .unwrap();
+
+    for (name, cycles) in SPEED_PRESETS.iter() {
+        let option = document().create_element("option").unwrap();
+        option.set_attribute("value", &cycles.to_string()).unwrap();
+        option.set_text_content(Some(name));
+        select.append_child(&option).unwrap();
+    }
+    select.set_value(&speed.get().cycles_per_frame.to_string());
+
+    let on_select = {
+        let (speed, select) = (speed.clone(), select.clone());
+        Closure::<dyn FnMut(Event)>::new(move |_: Event| {
+            if let Ok(cycles_per_frame) = select.value().parse() {
+                speed.set(Speed {
+                    cycles_per_frame,
+                    ..speed.get()
+                });
+            }
+        })
+    };
+    select
+        .add_event_listener_with_callback("change", on_select.as_ref().unchecked_ref())
+        .unwrap();
+    on_select.forget();
+
+    let turbo = document()
+        .get_element_by_id("turbo")
+        .expect("no turbo element")
+        .dyn_into::<HtmlInputElement>()
+        .unwrap();
+    let on_turbo = {
+        let turbo = turbo.clone();
+        Closure::<dyn FnMut(Event)>::new(move |_: Event| {
+            speed.set(Speed {
+                turbo: turbo.checked(),
+                ..speed.get()
+            });
+        })
+    };
+    turbo
+        .add_event_listener_with_callback("change", on_turbo.as_ref().unchecked_ref())
+        .unwrap();
+    on_turbo.forget();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FrameClock, IpsCounter, Speed, MAX_TICKS_PER_FRAME};
+
+    #[test]
+    fn ticks_at_60_hz() {
+        let mut clock = FrameClock::default();
+        assert_eq!(0, clock.advance(1000.0));
+
+        // A 100 Hz display doesn't tick on every frame
+        let ticks: u32 = (1..=101).map(|i| clock.advance(1000.0 + i as f64 * 10.0)).sum();
+        assert_eq!(60, ticks);
+
+        // A long pause is not caught up
+        assert_eq!(MAX_TICKS_PER_FRAME, clock.advance(10_000.0));
+        assert_eq!(1, clock.advance(10_020.0));
+
+        let turbo = Speed { turbo: true, ..Speed::default() };
+        assert_eq!(16, turbo.frames(2));
+    }
+
+    #[test]
+    fn instructions_per_second() {
+        let mut counter = IpsCounter::default();
+        for frame in 0..=60 {
+            counter.add(frame as f64 * 1000.0 / 60.0, 10);
+        }
+
+        assert_eq!(600, counter.ips);
+    }
+}
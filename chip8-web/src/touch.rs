@@ -0,0 +1,85 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use wasm_bindgen::prelude::*;
+use web_sys::{Element, PointerEvent};
+
+use crate::{
+    dom::{document, KeyHandler},
+    input::KEYPAD_LAYOUT,
+};
+
+/// Class added to a keypad button while it is held down
+const PRESSED_CLASS: &str = "pressed";
+
+/// Keeps track of which pointer holds which key, every finger is its own pointer
+#[derive(Default)]
+struct Pointers {
+    keys: HashMap<i32, u8>,
+}
+
+impl Pointers {
+    fn is_held(&self, key: u8) -> bool {
+        self.keys.values().any(|k| *k == key)
+    }
+}
+
+/// Builds the 4x4 on-screen keypad inside `#touchpad` and feeds its pointer
+/// events into the keypad of the emulator
+pub fn init_touchpad(set_key: KeyHandler) {
+    let pad = document().get_element_by_id("touchpad").expect("no touchpad element");
+    let pointers = Rc::new(RefCell::new(Pointers::default()));
+
+    for key in KEYPAD_LAYOUT {
+        let button = document().create_element("button").unwrap();
+        button.set_text_content(Some(&format!("{:X}", key)));
+
+        let on_down = {
+            let (set_key, pointers, button) = (set_key.clone(), pointers.clone(), button.clone());
+            Closure::<dyn FnMut(PointerEvent)>::new(move |e: PointerEvent| {
+                e.prevent_default();
+                pointers.borrow_mut().keys.insert(e.pointer_id(), key);
+                set_key(key, true);
+                set_pressed(&button, true);
+            })
+        };
+
+        let on_up = {
+            let (set_key, pointers, button) = (set_key.clone(), pointers.clone(), button.clone());
+            Closure::<dyn FnMut(PointerEvent)>::new(move |e: PointerEvent| {
+                let mut pointers = pointers.borrow_mut();
+                if pointers.keys.remove(&e.pointer_id()).is_none() {
+                    return;
+                }
+
+                // Another finger may still hold the same key
+                if !pointers.is_held(key) {
+                    set_key(key, false);
+                    set_pressed(&button, false);
+                }
+            })
+        };
+
+        button
+            .add_event_listener_with_callback("pointerdown", on_down.as_ref().unchecked_ref())
+            .unwrap();
+        for event in ["pointerup", "pointercancel", "pointerleave"] {
+            button
+                .add_event_listener_with_callback(event, on_up.as_ref().unchecked_ref())
+                .unwrap();
+        }
+
+        on_down.forget();
+        on_up.forget();
+
+        pad.append_child(&button).unwrap();
+    }
+}
+
+fn set_pressed(button: &Element, pressed: bool) {
+    let classes = button.class_list();
+    let _ = if pressed {
+        classes.add_1(PRESSED_CLASS)
+    } else {
+        classes.remove_1(PRESSED_CLASS)
+    };
+}
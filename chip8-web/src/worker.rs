@@ -0,0 +1,424 @@
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
+
+use chip8::{
+    app::{AppState, ExecError, RomError},
+    chip8::ch8_types::{DISPLAY_HEIGHT, DISPLAY_WIDTH},
+    render::{planes_from_vram, Palette},
+};
+use wasm_bindgen::prelude::*;
+use web_sys::{
+    console,
+    js_sys::{self, Object, Reflect, SharedArrayBuffer, Uint8Array},
+    DedicatedWorkerGlobalScope, MessageEvent, Worker, WorkerOptions, WorkerType,
+};
+
+use crate::{
+    audio::{init_audio, Beeper},
+    dom::{element, init_palette_select, on_click, request_animation_frame, window, FrameCallback, KeyHandler, Screen},
+    gamepad::GamepadInput,
+    initial_rom,
+    input::{init_keyboard, KeySource, KeySources},
+    rom::rom_info,
+    timing::{init_speed_controls, FrameClock, Speed, TICK_MILLIS},
+    touch::init_touchpad,
+};
+
+/// Bytes of bit planes in a frame, one per pixel
+const FRAME_SIZE: usize = DISPLAY_WIDTH * DISPLAY_HEIGHT;
+
+/// What the page tells the worker, every message is an object with a `type` field
+#[derive(Debug, Clone, PartialEq)]
+enum Command {
+    /// `{type: "load", rom: Uint8Array}`
+    Load(Vec<u8>),
+
+    /// `{type: "key", key, pressed}`
+    Key(u8, bool),
+
+    /// `{type: "speed", cycles, turbo}`
+    Speed(Speed),
+
+    /// `{type: "pause", paused}`
+    Pause(bool),
+
+    /// `{type: "reset"}`
+    Reset,
+}
+
+impl Command {
+    fn from_message(data: &JsValue) -> Option<Self> {
+        let command = match field(data, "type").as_string()?.as_str() {
+            "load" => Command::Load(Uint8Array::new(&field(data, "rom")).to_vec()),
+            "key" => Command::Key(field(data, "key").as_f64()? as u8, field(data, "pressed").is_truthy()),
+            "speed" => Command::Speed(Speed {
+                cycles_per_frame: field(data, "cycles").as_f64()? as usize,
+                turbo: field(data, "turbo").is_truthy(),
+            }),
+            "pause" => Command::Pause(field(data, "paused").is_truthy()),
+            "reset" => Command::Reset,
+            _ => return None,
+        };
+        Some(command)
+    }
+
+    fn to_message(&self) -> Object {
+        match self {
+            Command::Load(rom) => message("load", &[("rom", Uint8Array::from(&rom[..]).into())]),
+            Command::Key(key, pressed) => message(
+                "key",
+                &[("key", JsValue::from(*key)), ("pressed", JsValue::from(*pressed))],
+            ),
+            Command::Speed(speed) => message(
+                "speed",
+                &[
+                    ("cycles", JsValue::from(speed.cycles_per_frame as u32)),
+                    ("turbo", JsValue::from(speed.turbo)),
+                ],
+            ),
+            Command::Pause(paused) => message("pause", &[("paused", JsValue::from(*paused))]),
+            Command::Reset => message("reset", &[]),
+        }
+    }
+}
+
+/// Builds a message object of the given type
+fn message(kind: &str, fields: &[(&str, JsValue)]) -> Object {
+    let object = Object::new();
+    let _ = Reflect::set(&object, &"type".into(), &kind.into());
+    for (name, value) in fields {
+        let _ = Reflect::set(&object, &(*name).into(), value);
+    }
+    object
+}
+
+fn field(object: &JsValue, name: &str) -> JsValue {
+    Reflect::get(object, &name.into()).unwrap_or(JsValue::UNDEFINED)
+}
+
+/// The emulator as it runs inside the worker, without any DOM access
+struct Session {
+    app: AppState,
+
+    /// The running program as it was loaded, for resets
+    rom: Vec<u8>,
+    speed: Speed,
+    paused: bool,
+    clock: FrameClock,
+}
+
+impl Session {
+    fn new() -> Self {
+        Self {
+            app: AppState::new(&[]),
+            rom: Vec::new(),
+            speed: Speed::default(),
+            paused: false,
+            clock: FrameClock::default(),
+        }
+    }
+
+    fn handle(&mut self, command: Command) -> Result<(), RomError> {
+        match command {
+            Command::Load(rom) => {
+                self.app = AppState::try_new(&rom)?;
+                self.rom = rom;
+            }
+            Command::Key(key, pressed) => self.app.set_key(key, pressed),
+            Command::Speed(speed) => self.speed = speed,
+            Command::Pause(paused) => self.paused = paused,
+            Command::Reset => self.app = AppState::new(&self.rom),
+        }
+        Ok(())
+    }
+
+    /// Runs the frames that are due at `now` in milliseconds, returns true if the screen changed
+    ///
+    /// Nothing runs until a ROM was loaded. An instruction that can't be executed
+    /// pauses the session with PC left pointing at it.
+    fn advance(&mut self, now: f64) -> Result<bool, ExecError> {
+        // The clock keeps running while paused so resuming doesn't catch up
+        let frames = self.speed.frames(self.clock.advance(now));
+        if !self.paused && !self.rom.is_empty() {
+            for _ in 0..frames {
+                if let Err(e) = self.app.try_run_frame(self.speed.cycles_per_frame, |_, _| {}) {
+                    self.paused = true;
+                    return Err(e);
+                }
+            }
+        }
+        Ok(self.app.take_dirty().is_some())
+    }
+}
+
+/// Entry point of `worker.js`, runs the emulator at 60 Hz and posts its frames to the page
+///
+/// Frames are `{type: "frame", sound, planes}` messages. Rejected commands are
+/// reported as `{type: "error", message}`, errors that paused the emulator
+/// additionally carry `paused: true`. Once the page sent a
+/// `{type: "shared", buffer}` message with a `SharedArrayBuffer`, the planes are
+/// written into the buffer instead and left out of the messages.
+#[wasm_bindgen]
+pub fn run_worker() {
+    let scope = js_sys::global().unchecked_into::<DedicatedWorkerGlobalScope>();
+    let session = Rc::new(RefCell::new(Session::new()));
+    let shared: Rc<RefCell<Option<Uint8Array>>> = Rc::new(RefCell::new(None));
+
+    // The first frame after a load or a new buffer is always posted
+    let repaint = Rc::new(Cell::new(true));
+
+    let on_message = {
+        let (scope, session, shared, repaint) = (scope.clone(), session.clone(), shared.clone(), repaint.clone());
+        Closure::<dyn FnMut(MessageEvent)>::new(move |e: MessageEvent| {
+            let data = e.data();
+            if field(&data, "type").as_string().as_deref() == Some("shared") {
+                *shared.borrow_mut() = Some(Uint8Array::new(&field(&data, "buffer")));
+                repaint.set(true);
+                return;
+            }
+
+            let Some(command) = Command::from_message(&data) else {
+                return;
+            };
+            let load = matches!(command, Command::Load(_) | Command::Reset);
+            match session.borrow_mut().handle(command) {
+                Ok(()) => repaint.set(repaint.get() || load),
+                Err(e) => {
+                    let _ = scope.post_message(&message("error", &[("message", e.to_string().into())]));
+                }
+            }
+        })
+    };
+    scope.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+    on_message.forget();
+
+    let performance = scope.performance().expect("no performance in the worker");
+    let mut planes = vec![0; FRAME_SIZE];
+    let mut sound = false;
+    let on_tick = {
+        let scope = scope.clone();
+        Closure::<dyn FnMut()>::new(move || {
+            let mut session = session.borrow_mut();
+            let advanced = session.advance(performance.now()).unwrap_or_else(|e| {
+                let app = &session.app;
+                let text = format!("{} at 0x{:03X} ({})", e, app.pc, app.current_op());
+                let _ = scope.post_message(&message(
+                    "error",
+                    &[("message", text.into()), ("paused", JsValue::TRUE)],
+                ));
+                false
+            });
+            let dirty = advanced | repaint.replace(false);
+            let sounding = session.app.is_sounding();
+            if !dirty && sounding == sound {
+                return;
+            }
+            sound = sounding;
+
+            let mut fields = vec![("sound", JsValue::from(sound))];
+            planes_from_vram(&session.app.vram, &mut planes);
+            match shared.borrow().as_ref() {
+                Some(view) => view.copy_from(&planes),
+                None => fields.push(("planes", Uint8Array::from(&planes[..]).into())),
+            }
+            let _ = scope.post_message(&message("frame", &fields));
+        })
+    };
+    scope
+        .set_interval_with_callback_and_timeout_and_arguments_0(
+            on_tick.as_ref().unchecked_ref(),
+            TICK_MILLIS.floor() as i32,
+        )
+        .expect("setInterval failed");
+    on_tick.forget();
+
+    // Messages sent before the handler was set are lost, so the page waits for this one
+    scope.post_message(&message("ready", &[])).unwrap();
+}
+
+/// Runs the emulator in a dedicated worker loaded from `script_url`, needs the elements of `worker.html`
+///
+/// The page only presents the frames, plays the beep and forwards the input and
+/// the controls. Frames are shared through a `SharedArrayBuffer` if the page is
+/// cross-origin isolated and copied into every message otherwise.
+#[wasm_bindgen]
+pub fn start_in_worker(script_url: &str) -> Result<(), JsValue> {
+    let options = WorkerOptions::new();
+    options.set_type(WorkerType::Module);
+    let worker = Worker::new_with_options(script_url, &options)?;
+
+    let send = {
+        let worker = worker.clone();
+        move |command: Command| {
+            if let Err(e) = worker.post_message(&command.to_message()) {
+                console::error_2(&JsValue::from_str("could not message the worker:"), &e);
+            }
+        }
+    };
+
+    let set_key: KeyHandler = {
+        let send = send.clone();
+        Rc::new(move |key, pressed| send(Command::Key(key, pressed)))
+    };
+    let sources = Rc::new(RefCell::new(KeySources::default()));
+    init_keyboard(KeySources::handler(&sources, KeySource::Keyboard, set_key.clone()));
+    init_touchpad(KeySources::handler(&sources, KeySource::Touch, set_key.clone()));
+    let gamepad_keys = KeySources::handler(&sources, KeySource::Gamepad, set_key);
+
+    let paused = Rc::new(Cell::new(false));
+    on_click(&element("run"), {
+        let (send, paused) = (send.clone(), paused.clone());
+        move || {
+            paused.set(!paused.get());
+            send(Command::Pause(paused.get()));
+            element("run").set_text_content(Some(if paused.get() { "Run" } else { "Pause" }));
+        }
+    });
+    on_click(&element("reset"), {
+        let send = send.clone();
+        move || send(Command::Reset)
+    });
+
+    let isolated = Reflect::get(&window(), &"crossOriginIsolated".into())
+        .map(|v| v.is_truthy())
+        .unwrap_or(false);
+    let shared = isolated.then(|| SharedArrayBuffer::new(FRAME_SIZE as u32));
+    let view = shared.as_ref().map(|buffer| Uint8Array::new(buffer));
+
+    let screen = Rc::new(RefCell::new(Screen::new(element("canvas").dyn_into()?)));
+    let planes = Rc::new(RefCell::new(vec![0; FRAME_SIZE]));
+    let palette = Rc::new(Cell::new(Palette::default()));
+    {
+        let (screen, planes, palette) = (screen.clone(), planes.clone(), palette.clone());
+        init_palette_select(move |p| {
+            palette.set(p);
+            screen
+                .borrow_mut()
+                .present(&planes.borrow(), DISPLAY_WIDTH, DISPLAY_HEIGHT, &p);
+        });
+    }
+
+    let beeper = Rc::new(RefCell::new(Beeper::default()));
+    init_audio(beeper.clone());
+    let sound = Rc::new(Cell::new(false));
+
+    let (rom, name) = initial_rom();
+    let mut gamepad = GamepadInput::default();
+    gamepad.mapping = name
+        .as_deref()
+        .and_then(rom_info)
+        .map(|info| info.gamepad)
+        .unwrap_or_default();
+    let mut rom = Some(rom);
+
+    let on_message = {
+        let (worker, send, sound, paused) = (worker.clone(), send.clone(), sound.clone(), paused.clone());
+        Closure::<dyn FnMut(MessageEvent)>::new(move |e: MessageEvent| {
+            let data = e.data();
+            match field(&data, "type").as_string().as_deref() {
+                Some("ready") => {
+                    if let Some(buffer) = &shared {
+                        let _ = worker.post_message(&message("shared", &[("buffer", buffer.into())]));
+                    }
+                    if let Some(rom) = rom.take() {
+                        send(Command::Load(rom));
+                    }
+                }
+                Some("frame") => {
+                    let mut planes = planes.borrow_mut();
+                    match &view {
+                        Some(view) => view.copy_to(&mut planes),
+                        None => Uint8Array::new(&field(&data, "planes")).copy_to(&mut planes),
+                    }
+                    screen
+                        .borrow_mut()
+                        .present(&planes, DISPLAY_WIDTH, DISPLAY_HEIGHT, &palette.get());
+                    sound.set(field(&data, "sound").is_truthy());
+                }
+                Some("error") => {
+                    console::error_1(&field(&data, "message"));
+                    // The worker paused itself, the next click on #run resumes it
+                    if field(&data, "paused").is_truthy() {
+                        paused.set(true);
+                        element("run").set_text_content(Some("Run"));
+                    }
+                }
+                _ => {}
+            }
+        })
+    };
+    worker.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+    on_message.forget();
+
+    // The worker only posts frames that changed, so the input is polled on the page's own frames
+    let speed = Rc::new(Cell::new(Speed::default()));
+    init_speed_controls(speed.clone());
+    let mut sent_speed = speed.get();
+
+    let frame: Rc<RefCell<Option<FrameCallback>>> = Rc::new(RefCell::new(None));
+    let next = frame.clone();
+    *frame.borrow_mut() = Some(Closure::new(move |_: f64| {
+        gamepad.poll(|key, pressed| gamepad_keys(key, pressed));
+        if speed.get() != sent_speed {
+            sent_speed = speed.get();
+            send(Command::Speed(sent_speed));
+        }
+
+        // The timers stand still while paused, so does the beep
+        let sounding = sound.get() && !paused.get();
+        beeper.borrow_mut().update(sounding as u8);
+
+        request_animation_frame(next.borrow().as_ref().unwrap());
+    }));
+    request_animation_frame(frame.borrow().as_ref().unwrap());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chip8::app::ExecError;
+
+    use super::{Command, Session};
+    use crate::{timing::TICK_MILLIS, IBM_LOGO};
+
+    #[test]
+    fn session_commands() {
+        let mut session = Session::new();
+        assert!(session.handle(Command::Load(Vec::new())).is_err());
+
+        // The first call only starts the clock, nothing runs without a ROM
+        session.advance(0.0).unwrap();
+        assert_eq!(Ok(false), session.advance(TICK_MILLIS * 2.0));
+        assert_eq!(0x200, session.app.pc);
+
+        session.handle(Command::Load(IBM_LOGO.to_vec())).unwrap();
+        assert_eq!(Ok(true), session.advance(TICK_MILLIS * 4.0));
+        assert_ne!(0x200, session.app.pc);
+
+        session.handle(Command::Pause(true)).unwrap();
+        let pc = session.app.pc;
+        session.advance(TICK_MILLIS * 8.0).unwrap();
+        assert_eq!(pc, session.app.pc);
+
+        session.handle(Command::Reset).unwrap();
+        assert_eq!(0x200, session.app.pc);
+    }
+
+    #[test]
+    fn session_pauses_on_errors() {
+        let mut session = Session::new();
+        // 0000
+        session.handle(Command::Load(vec![0x00, 0x00])).unwrap();
+        session.advance(0.0).unwrap();
+
+        assert!(matches!(
+            session.advance(TICK_MILLIS * 2.0),
+            Err(ExecError::Unsupported(_))
+        ));
+        assert!(session.paused);
+        assert_eq!(0x200, session.app.pc);
+        assert_eq!(Ok(false), session.advance(TICK_MILLIS * 4.0));
+    }
+}
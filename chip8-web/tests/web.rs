@@ -1,13 +1,116 @@
-//! Test suite for the Web and headless browsers.
+//! Tests of the exported API, they don't touch the DOM so they run under Node
+//!
+//! `wasm-pack test --node`
 
 #![cfg(target_arch = "wasm32")]
 
 extern crate wasm_bindgen_test;
+use chip8_web::{load_rom, Emulator, IBM_LOGO};
 use wasm_bindgen_test::*;
 
-wasm_bindgen_test_configure!(run_in_browser);
+// F30A LD V3, K
+// 1202 JP 0x202
+const WAIT_FOR_KEY: [u8; 4] = [0xF3, 0x0A, 0x12, 0x02];
 
 #[wasm_bindgen_test]
-fn pass() {
-    assert_eq!(1 + 1, 2);
+fn load() {
+    let emu = Emulator::new(IBM_LOGO).unwrap();
+    assert_eq!(0x200, emu.pc());
+    assert_eq!(0, emu.index());
+    assert_eq!(0, emu.sp());
+    assert_eq!(vec![0; 16], emu.registers());
+}
+
+#[wasm_bindgen_test]
+fn bad_roms() {
+    let error = Emulator::new(&[]).err().unwrap();
+    assert_eq!(Some("the ROM is empty".to_string()), error.as_string());
+
+    let error = Emulator::new(&[0; 0x1000]).err().unwrap();
+    assert!(error.as_string().unwrap().contains("4096 bytes"));
+
+    // The page entry point was never started
+    assert!(load_rom(IBM_LOGO).is_err());
+}
+
+#[wasm_bindgen_test]
+fn frames() {
+    let mut emu = Emulator::new(IBM_LOGO).unwrap();
+    assert_eq!(64, emu.width());
+    assert_eq!(32, emu.height());
+
+    let framebuffer = emu.framebuffer();
+    assert_eq!(emu.width() * emu.height(), framebuffer.len());
+    assert!(framebuffer.iter().all(|pixel| *pixel == 0));
+
+    emu.step().unwrap();
+    assert_eq!(0x202, emu.pc());
+
+    for _ in 0..10 {
+        emu.run_frame().unwrap();
+    }
+    let framebuffer = emu.framebuffer();
+    assert!(framebuffer.contains(&1));
+    assert!(framebuffer.iter().all(|pixel| *pixel <= 1));
+}
+
+#[wasm_bindgen_test]
+fn unsupported_instruction() {
+    // 6001 LD V0, 0x01
+    // 0000
+    let mut emu = Emulator::new(&[0x60, 0x01, 0x00, 0x00]).unwrap();
+    let error = emu.run_frame().err().unwrap().as_string().unwrap();
+    assert!(error.starts_with("unsupported instruction at 0x202"), "{}", error);
+    assert_eq!(0x202, emu.pc());
+    assert_eq!(1, emu.registers()[0]);
+
+    // PC stays on the instruction, so it fails again
+    assert!(emu.step().is_err());
+    assert_eq!(0x202, emu.pc());
+}
+
+#[wasm_bindgen_test]
+fn cycles_per_frame() {
+    let mut emu = Emulator::new(IBM_LOGO).unwrap();
+    emu.cycles_per_frame = 3;
+    emu.run_frame().unwrap();
+    assert_eq!(0x206, emu.pc());
+}
+
+#[wasm_bindgen_test]
+fn keys() {
+    let mut emu = Emulator::new(&WAIT_FOR_KEY).unwrap();
+    emu.run_frame().unwrap();
+    assert_eq!(0x200, emu.pc());
+
+    emu.key_down(0xB);
+    emu.step().unwrap();
+    assert_eq!(0x202, emu.pc());
+    assert_eq!(0xB, emu.registers()[3]);
+
+    emu.key_up(0xB);
+    emu.reset();
+    emu.run_frame().unwrap();
+    assert_eq!(0x200, emu.pc());
+}
+
+#[wasm_bindgen_test]
+fn save_state() {
+    let mut emu = Emulator::new(IBM_LOGO).unwrap();
+    emu.run_frame().unwrap();
+    let state = emu.save_state();
+    let (pc, framebuffer) = (emu.pc(), emu.framebuffer());
+
+    emu.run_frame().unwrap();
+    assert_ne!(pc, emu.pc());
+
+    emu.load_state(&state).unwrap();
+    assert_eq!(pc, emu.pc());
+    assert_eq!(framebuffer, emu.framebuffer());
+    assert_eq!(state, emu.save_state());
+
+    // A broken state leaves the emulator untouched
+    assert!(emu.load_state(&state[..16]).is_err());
+    assert!(emu.load_state(b"not a save state").is_err());
+    assert_eq!(pc, emu.pc());
 }
@@ -0,0 +1,5 @@
+// Runs the emulator off the main thread, started by worker.html
+import init, { run_worker } from './pkg/chip8_web.js';
+
+await init();
+run_worker();
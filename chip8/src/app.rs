@@ -1,15 +1,162 @@
-use core::cell::RefCell;
+use core::{cell::RefCell, fmt};
 
 use crate::{
+    audio::{AudioPattern, AUDIO_PATTERN_SIZE, DEFAULT_PITCH},
     chip8::{
         self,
-        ch8_types::{self, MemoryAddress, Registers, Stack, DISPLAY_HEIGHT, DISPLAY_WIDTH, REGISTER_SIZE, STACK_SIZE, VRAM},
+        ch8_types::{
+            self, Keypad, MemoryAddress, Registers, Stack, DISPLAY_HEIGHT, DISPLAY_WIDTH, KEYPAD_SIZE, MEMORY_SIZE, REGISTER_SIZE,
+            STACK_SIZE, VRAM,
+        },
         Ops,
     },
-    display::{self, DisplayController, FONT},
+    display::{self, DirtyRect, DirtyTracker, DisplayController},
+    font::{FontConfig, FontError},
     memory::Memory,
 };
 
+/// Address the program is loaded to, the memory below belongs to the interpreter
+pub const PROGRAM_START: usize = 0x200;
+
+/// Size of the largest program that fits into memory
+pub const MAX_PROGRAM_SIZE: usize = MEMORY_SIZE - PROGRAM_START;
+
+/// Seed of the random number generator used by `RND` until [AppState::seed_random] is called
+pub const DEFAULT_SEED: u32 = 0x2545_F491;
+
+/// Reasons a program can't be loaded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RomError {
+    Empty,
+
+    /// Size of the rejected program in bytes
+    TooLarge(usize),
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RomError::Empty => write!(f, "the ROM is empty"),
+            RomError::TooLarge(size) => {
+                write!(f, "the ROM has {} bytes, at most {} fit into memory", size, MAX_PROGRAM_SIZE)
+            }
+        }
+    }
+}
+
+/// Reasons an instruction can't be executed
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExecError {
+    /// The instruction is not implemented or not an instruction at all
+    Unsupported(Ops),
+
+    /// `CALL` with all stack entries in use
+    StackOverflow,
+
+    /// `RET` without a `CALL`
+    StackUnderflow,
+}
+
+impl fmt::Display for ExecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExecError::Unsupported(_) => write!(f, "unsupported instruction"),
+            ExecError::StackOverflow => write!(f, "stack overflow"),
+            ExecError::StackUnderflow => write!(f, "stack underflow"),
+        }
+    }
+}
+
+/// Identifies save states, followed by [SAVE_STATE_VERSION]
+const SAVE_STATE_MAGIC: &[u8; 4] = b"CH8S";
+const SAVE_STATE_VERSION: u8 = 1;
+
+/// Size of a save state in bytes, see [AppState::save_state] for the layout
+pub const SAVE_STATE_SIZE: usize = SAVE_STATE_MAGIC.len()
+    + 1 // version
+    + 2 + 2 + 1 // pc, I, sp
+    + REGISTER_SIZE
+    + STACK_SIZE * 2
+    + 4 // delay timer, sound timer, pitch, flags
+    + AUDIO_PATTERN_SIZE
+    + MEMORY_SIZE
+    + PACKED_VRAM_SIZE;
+
+/// Size of the VRAM with 8 pixels packed into each byte
+const PACKED_VRAM_SIZE: usize = DISPLAY_WIDTH * DISPLAY_HEIGHT / 8;
+
+/// 64-bit FNV-1a hash, stable across platforms and versions
+pub fn fnv1a(data: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in data {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+/// Reasons a save state can't be loaded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateError {
+    /// The data doesn't start with the save state magic, has the wrong size
+    /// or holds registers that point outside of memory
+    Invalid,
+
+    /// The state was written by a newer version
+    UnsupportedVersion(u8),
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateError::Invalid => write!(f, "not a save state"),
+            StateError::UnsupportedVersion(v) => write!(f, "unsupported save state version {}", v),
+        }
+    }
+}
+
+// Bits of the flags byte in a save state
+const FLAG_DISPLAY_WAIT: u8 = 0x1;
+const FLAG_VBLANK: u8 = 0x2;
+const FLAG_WAITING_FOR_VBLANK: u8 = 0x4;
+const FLAG_AUDIO_PATTERN: u8 = 0x8;
+const FLAG_WRAP_SPRITES: u8 = 0x10;
+
+/// Writes the fields of a save state one after another
+struct StateWriter<'a> {
+    data: &'a mut [u8],
+    pos: usize,
+}
+
+impl StateWriter<'_> {
+    fn write(&mut self, bytes: &[u8]) {
+        self.data[self.pos..self.pos + bytes.len()].copy_from_slice(bytes);
+        self.pos += bytes.len();
+    }
+}
+
+/// Reads the fields of a save state one after another
+struct StateReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> StateReader<'a> {
+    fn read(&mut self, len: usize) -> &'a [u8] {
+        self.pos += len;
+        &self.data[self.pos - len..self.pos]
+    }
+
+    fn read_u8(&mut self) -> u8 {
+        self.read(1)[0]
+    }
+
+    fn read_u16(&mut self) -> u16 {
+        let bytes = self.read(2);
+        u16::from_be_bytes([bytes[0], bytes[1]])
+    }
+}
+
 /// Holds the State of the emulator
 /// CHIP-8 has the following components:
 /// - Memory: CHIP-8 has direct access to up to 4 kilobytes of RAM
@@ -30,137 +177,540 @@ pub struct AppState {
     memory: Memory,
     stack: Stack,
     pub vram: VRAM,
+    dirty: DirtyTracker,
+    keypad: Keypad,
+    font: FontConfig,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    audio_pattern: Option<AudioPattern>,
+    pitch: u8,
+
+    /// xorshift32 state of `RND`, not part of save states
+    rng: u32,
+
+    /// Emulates the COSMAC VIP behaviour of `DRW` waiting for the next vertical blank,
+    /// which limits the program to one sprite draw per frame
+    pub display_wait: bool,
+
+    /// Sprites crossing an edge of the display continue on the opposite side like
+    /// on XO-CHIP, otherwise they are clipped like on the COSMAC VIP and SUPER-CHIP
+    pub wrap_sprites: bool,
+    vblank: bool,
+
+    /// The sound timer was running during the last tick
+    sounding: bool,
+    waiting_for_vblank: bool,
 }
 
 impl AppState {
     pub fn new(prog: &[u8]) -> Self {
+        Self::with_font(prog, FontConfig::default())
+    }
+
+    /// Like [`AppState::new`], but rejects programs that don't fit into memory
+    pub fn try_new(prog: &[u8]) -> Result<Self, RomError> {
+        if prog.is_empty() {
+            return Err(RomError::Empty);
+        }
+        if prog.len() > MAX_PROGRAM_SIZE {
+            return Err(RomError::TooLarge(prog.len()));
+        }
+        Ok(Self::new(prog))
+    }
+
+    /// Creates the emulator with the given font set loaded at the configured address
+    ///
+    /// Panics if the fonts reach into the program, see [AppState::try_with_font].
+    pub fn with_font(prog: &[u8], font: FontConfig) -> Self {
+        Self::try_with_font(prog, font).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Like [AppState::with_font], but rejects fonts that don't end below the program
+    /// instead of panicking, [FontConfig::clamped] moves them down instead
+    pub fn try_with_font(prog: &[u8], font: FontConfig) -> Result<Self, FontError> {
+        let font = font.validate()?;
+
         // Initialize Memory Layout
         let mut memory = Memory::default();
-        memory.load_at_address(0x50, &FONT);
-        memory.load_at_address(0x200, prog);
+        memory.load_at_address(font.address as usize, font.set.small());
+        if let Some(large) = font.set.large() {
+            memory.load_at_address(font.large_glyph_address(0) as usize, large);
+        }
+        memory.load_at_address(PROGRAM_START, prog);
 
-        Self {
-            pc: 0x200,
+        // The first frame has to be drawn completely
+        let mut dirty = DirtyTracker::default();
+        dirty.mark_all();
+
+        Ok(Self {
+            pc: PROGRAM_START,
             I: Default::default(),
             sp: Default::default(),
             registers: [0; REGISTER_SIZE],
             memory: memory,
             stack: [0; STACK_SIZE],
             vram: [[false; DISPLAY_WIDTH]; DISPLAY_HEIGHT],
+            dirty,
+            keypad: [false; KEYPAD_SIZE],
+            font,
+            delay_timer: 0,
+            sound_timer: 0,
+            audio_pattern: None,
+            pitch: DEFAULT_PITCH,
+            rng: DEFAULT_SEED,
+            display_wait: false,
+            wrap_sprites: false,
+            sounding: false,
+            vblank: false,
+            waiting_for_vblank: false,
             //display: Chip8Display::default(),
-        }
+        })
     }
 
     fn reset(&mut self) {}
 
-    fn stack_push(&mut self, value: MemoryAddress) {
+    /// Returns the area of the VRAM that changed since the last call
+    /// and resets the tracking
+    pub fn take_dirty(&mut self) -> Option<DirtyRect> {
+        self.dirty.take()
+    }
+
+    fn stack_push(&mut self, value: MemoryAddress) -> Result<(), ExecError> {
+        if self.sp >= STACK_SIZE {
+            return Err(ExecError::StackOverflow);
+        }
         self.stack[self.sp] = value;
         self.sp += 1;
+        Ok(())
     }
 
-    fn stack_pop(&mut self) -> MemoryAddress {
-        let value = self.stack[self.sp];
+    fn stack_pop(&mut self) -> Result<MemoryAddress, ExecError> {
+        if self.sp == 0 {
+            return Err(ExecError::StackUnderflow);
+        }
         self.sp -= 1;
-        value
+        Ok(self.stack[self.sp])
+    }
+
+    /// Sets the state of the given key (0x0 - 0xF) on the hex keypad
+    pub fn set_key(&mut self, key: u8, pressed: bool) {
+        self.keypad[(key & 0xF) as usize] = pressed;
+    }
+
+    /// Returns true if the given key (0x0 - 0xF) is held down
+    pub fn is_key_pressed(&self, key: u8) -> bool {
+        self.keypad[(key & 0xF) as usize]
+    }
+
+    /// Executes one frame worth of instructions and ticks the timers afterwards,
+    /// returns the number of instructions executed
+    ///
+    /// With [AppState::display_wait] enabled the frame ends early as soon as
+    /// `DRW` blocks on the vertical blank.
+    pub fn run_frame(&mut self, cycles: usize) -> usize {
+        self.try_run_frame(cycles, |_, _| {})
+            .unwrap_or_else(|e| panic!("{} at 0x{:03X} ({})", e, self.pc, self.current_op()))
+    }
+
+    /// Like [AppState::run_frame], but stops at the first instruction that can't be
+    /// executed and calls `on_step` with the address and the instruction after every
+    /// executed one
+    ///
+    /// The timers don't tick if the frame ends with an error.
+    pub fn try_run_frame(&mut self, cycles: usize, mut on_step: impl FnMut(usize, &Ops)) -> Result<usize, ExecError> {
+        let mut i = 0;
+        while i < cycles {
+            let pc = self.pc;
+            let op = self.try_step()?;
+            on_step(pc, &op);
+            i += 1;
+            if self.waiting_for_vblank {
+                break;
+            }
+        }
+
+        self.tick_timers();
+        Ok(i)
+    }
+
+    /// Decrements the delay and sound timers and signals the vertical blank,
+    /// has to be called at 60 Hz
+    pub fn tick_timers(&mut self) {
+        self.sounding = self.sound_timer > 0;
+        self.delay_timer = self.delay_timer.saturating_sub(1);
+        self.sound_timer = self.sound_timer.saturating_sub(1);
+
+        self.vblank = true;
+        self.waiting_for_vblank = false;
+    }
+
+    /// Returns true if the speaker beeped during the frame that ended with the last tick
+    ///
+    /// The timers already ticked after a frame, so `sound_timer > 0` misses the last
+    /// tick of every beep and a beep of a single tick completely.
+    pub fn is_sounding(&self) -> bool {
+        self.sounding
+    }
+
+    /// Returns the XO-CHIP audio pattern, None until the program loaded one with `F002`
+    pub fn audio_pattern(&self) -> Option<&AudioPattern> {
+        self.audio_pattern.as_ref()
+    }
+
+    /// Returns the XO-CHIP playback pitch set by `Fx3A`
+    pub fn pitch(&self) -> u8 {
+        self.pitch
+    }
+
+    /// Restarts the random number generator used by `RND`, runs with the same seed
+    /// and input produce the same results
+    pub fn seed_random(&mut self, seed: u32) {
+        // xorshift gets stuck at 0
+        self.rng = if seed == 0 { DEFAULT_SEED } else { seed };
+    }
+
+    fn next_random(&mut self) -> u8 {
+        let mut x = self.rng;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.rng = x;
+        (x >> 24) as u8
+    }
+
+    /// Returns true if the program ended in a jump to itself, the usual way to stop
+    pub fn is_halted(&self) -> bool {
+        self.current_op() == Ops::JP(self.pc as MemoryAddress)
+    }
+
+    /// Returns true while `DRW` is blocked waiting for the next timer tick
+    pub fn is_waiting_for_vblank(&self) -> bool {
+        self.waiting_for_vblank
+    }
+
+    /// Returns a stable 64-bit FNV-1a hash of the framebuffer
+    ///
+    /// The VRAM is hashed row by row with 8 pixels packed into each byte (MSB first),
+    /// so the value only depends on the pixels and can be stored in golden files
+    pub fn frame_hash(&self) -> u64 {
+        fnv1a(&self.packed_vram())
+    }
+
+    /// Packs the VRAM row by row with 8 pixels per byte, MSB first
+    fn packed_vram(&self) -> [u8; PACKED_VRAM_SIZE] {
+        let mut packed = [0; PACKED_VRAM_SIZE];
+
+        for (byte, chunk) in packed.iter_mut().zip(self.vram.iter().flat_map(|row| row.chunks(8))) {
+            for (bit, pixel) in chunk.iter().enumerate() {
+                if *pixel {
+                    *byte |= 0x80 >> bit;
+                }
+            }
+        }
+
+        packed
+    }
+
+    /// Returns the registers V0 - VF
+    pub fn registers(&self) -> &Registers {
+        &self.registers
+    }
+
+    /// Returns the return addresses on the call stack, the innermost call last
+    pub fn stack(&self) -> &[MemoryAddress] {
+        &self.stack[..self.sp.min(STACK_SIZE)]
+    }
+
+    pub fn memory(&self) -> &ch8_types::Memory {
+        self.memory.as_bytes()
+    }
+
+    /// Serializes the machine state into a fixed-size snapshot
+    ///
+    /// All values are big endian, in order: magic, version, PC, I, SP, V0 - VF, the
+    /// whole stack, delay timer, sound timer, pitch, flags, audio pattern, memory and
+    /// the VRAM packed like in [AppState::frame_hash]. The font configuration and the
+    /// keypad are not part of the state.
+    pub fn save_state(&self) -> [u8; SAVE_STATE_SIZE] {
+        let mut data = [0; SAVE_STATE_SIZE];
+        let mut out = StateWriter { data: &mut data, pos: 0 };
+
+        out.write(SAVE_STATE_MAGIC);
+        out.write(&[SAVE_STATE_VERSION]);
+        out.write(&(self.pc as u16).to_be_bytes());
+        out.write(&self.I.to_be_bytes());
+        out.write(&[self.sp as u8]);
+        out.write(&self.registers);
+        for address in self.stack.iter() {
+            out.write(&address.to_be_bytes());
+        }
+
+        let mut flags = 0;
+        for (set, flag) in [
+            (self.display_wait, FLAG_DISPLAY_WAIT),
+            (self.wrap_sprites, FLAG_WRAP_SPRITES),
+            (self.vblank, FLAG_VBLANK),
+            (self.waiting_for_vblank, FLAG_WAITING_FOR_VBLANK),
+            (self.audio_pattern.is_some(), FLAG_AUDIO_PATTERN),
+        ] {
+            if set {
+                flags |= flag;
+            }
+        }
+        out.write(&[self.delay_timer, self.sound_timer, self.pitch, flags]);
+        out.write(self.audio_pattern.as_ref().unwrap_or(&[0; AUDIO_PATTERN_SIZE]));
+        out.write(self.memory.as_bytes());
+        out.write(&self.packed_vram());
+
+        data
+    }
+
+    /// Restores a snapshot written by [AppState::save_state]
+    ///
+    /// States with PC, I, SP or a stack entry outside of memory and the stack are
+    /// rejected, nothing is changed then.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        if data.len() != SAVE_STATE_SIZE || !data.starts_with(SAVE_STATE_MAGIC) {
+            return Err(StateError::Invalid);
+        }
+        if data[SAVE_STATE_MAGIC.len()] != SAVE_STATE_VERSION {
+            return Err(StateError::UnsupportedVersion(data[SAVE_STATE_MAGIC.len()]));
+        }
+
+        let mut input = StateReader {
+            data,
+            pos: SAVE_STATE_MAGIC.len() + 1,
+        };
+
+        let (pc, index, sp) = (input.read_u16() as usize, input.read_u16(), input.read_u8() as usize);
+        if pc >= MEMORY_SIZE - 1 || index as usize >= MEMORY_SIZE || sp > STACK_SIZE {
+            return Err(StateError::Invalid);
+        }
+        let mut registers = [0; REGISTER_SIZE];
+        registers.copy_from_slice(input.read(REGISTER_SIZE));
+        let mut stack = [0; STACK_SIZE];
+        for address in stack.iter_mut() {
+            *address = input.read_u16();
+        }
+        // RET continues at a stack entry just like at PC
+        if stack.iter().any(|address| *address as usize >= MEMORY_SIZE - 1) {
+            return Err(StateError::Invalid);
+        }
+
+        self.pc = pc;
+        self.I = index;
+        self.sp = sp;
+        self.registers = registers;
+        self.stack = stack;
+
+        self.delay_timer = input.read_u8();
+        self.sound_timer = input.read_u8();
+        self.sounding = self.sound_timer > 0;
+        self.pitch = input.read_u8();
+        let flags = input.read_u8();
+        self.display_wait = flags & FLAG_DISPLAY_WAIT != 0;
+        self.wrap_sprites = flags & FLAG_WRAP_SPRITES != 0;
+        self.vblank = flags & FLAG_VBLANK != 0;
+        self.waiting_for_vblank = flags & FLAG_WAITING_FOR_VBLANK != 0;
+
+        let mut pattern = [0; AUDIO_PATTERN_SIZE];
+        pattern.copy_from_slice(input.read(AUDIO_PATTERN_SIZE));
+        self.audio_pattern = if flags & FLAG_AUDIO_PATTERN != 0 { Some(pattern) } else { None };
+
+        self.memory.as_bytes_mut().copy_from_slice(input.read(MEMORY_SIZE));
+
+        for row in self.vram.iter_mut() {
+            for chunk in row.chunks_mut(8) {
+                let byte = input.read_u8();
+                for (bit, pixel) in chunk.iter_mut().enumerate() {
+                    *pixel = byte & (0x80 >> bit) != 0;
+                }
+            }
+        }
+
+        self.dirty.mark_all();
+        Ok(())
+    }
+
+    /// Decodes the instruction at PC without executing it
+    pub fn current_op(&self) -> Ops {
+        let memory = self.memory.as_bytes();
+        [memory[self.pc % MEMORY_SIZE], memory[(self.pc + 1) % MEMORY_SIZE]].into()
     }
 
     /// Execute next instruction
     /// Returns the Opcode for Debug Purposes
+    ///
+    /// Panics on instructions that can't be executed, see [AppState::try_step]
     pub fn step(&mut self) -> Ops {
+        self.try_step().unwrap_or_else(|e| panic!("{} at 0x{:03X} ({})", e, self.pc, self.current_op()))
+    }
+
+    /// Executes the next instruction, the state is left untouched if it can't be executed
+    pub fn try_step(&mut self) -> Result<Ops, ExecError> {
         let instr: Ops = self.memory.get_instruction(self.pc).into();
-        self.exec_op(instr.clone());
-        instr
+        self.exec_op(instr.clone())?;
+        Ok(instr)
     }
 
     /// Executes the given Opcode
-    fn exec_op(&mut self, i: Ops) {
+    fn exec_op(&mut self, i: Ops) -> Result<(), ExecError> {
         //let display = self.getVramController();
         let display = DisplayController {};
         let mem = RefCell::new(&mut self.vram);
         
         match i {
-            Ops::CLS => display.clear_vram(*mem.borrow_mut()),
+            Ops::CLS => {
+                display.clear_vram(*mem.borrow_mut());
+                self.dirty.mark_all();
+            }
             Ops::RET => {
-                let v = self.stack_pop();
-                self.pc = v as usize;
-                return;
+                // Continues behind the CALL
+                self.pc = self.stack_pop()? as usize;
             }
             Ops::JP(addr) => {
                 self.pc = addr as usize;
-                return;
+                return Ok(());
             }
             Ops::CALL(addr) => {
-                let v: u16 = self.pc.try_into().unwrap();
-                self.stack_push(v);
+                self.stack_push(self.pc as MemoryAddress)?;
                 
                 self.pc = addr as usize;
-                return;
+                return Ok(());
             }
             Ops::DRW(rx, ry, n) => {
-                let (x, y) = (self.registers[rx],  self.registers[ry]);
-                
+                if self.display_wait {
+                    if !self.vblank {
+                        // Execute this instruction again after the next tick
+                        self.waiting_for_vblank = true;
+                        return Ok(());
+                    }
+                    self.vblank = false;
+                }
+
+                // The start position always wraps, the sprite itself is clipped or wrapped
+                let x = self.registers[rx] as usize % DISPLAY_WIDTH;
+                let y = self.registers[ry] as usize % DISPLAY_HEIGHT;
+                let n = n as usize;
+
                 self.registers[0xF] = 0;
 
                 //self.registers[0xF] = display.draw_onto(*mem.borrow_mut(), x, y, n);
                 
                 let mut i = 0;
                 while i < n {
+                    let row = match y + i {
+                        row if row < DISPLAY_HEIGHT => row,
+                        row if self.wrap_sprites => row % DISPLAY_HEIGHT,
+                        _ => break,
+                    };
+
                     // get sprite data from loaded memory
-                    let a = self.I + (i as u16);
-                    let data = self.memory.get_u8(a.into());
+                    let data = self.memory.get_u8(self.I as usize + i);
 
                     // transfer sprite to vram
-                    self.registers[0xF] = display.draw_onto(*mem.borrow_mut(), x as usize, (y + i) as usize, *data);
+                    self.registers[0xF] = display.draw_row(*mem.borrow_mut(), x, row, *data, self.wrap_sprites);
                     i += 1;
                 }
+
+                if self.wrap_sprites && (x + 8 > DISPLAY_WIDTH || y + n > DISPLAY_HEIGHT) {
+                    self.dirty.mark_all();
+                } else {
+                    self.dirty.mark(x, y, 8, n);
+                }
             },
             Ops::LD_V(rx, data) => {
                 self.registers[rx] = data;
             }
             Ops::ADD_V(rx, data) => {
-                self.registers[rx] += data;
+                // 7xkk doesn't touch VF, the sum simply wraps
+                self.registers[rx] = self.registers[rx].wrapping_add(data);
             }
             Ops::SET_I(addr) => {
                 self.I = addr;
             }
 
             // Arbitrary, unhandled Data, possibly unimplemented opcode
-            Ops::Data(data) => panic!("Tried executing unhandled opcode, data@{}pc: {:?}", self.pc, data),
-            Ops::SI(_, _) => todo!(),
-            Ops::SIN(_, _) => todo!(),
-            Ops::SVI(_, _) => todo!(),
-            Ops::SIV(_, _) => todo!(),
-            Ops::ORV(_, _) => todo!(),
-            Ops::ANDV(_, _) => todo!(),
-            Ops::XORV(_, _) => todo!(),
-            Ops::ADDVC(_, _) => todo!(),
-            Ops::SUBVC(_, _) => todo!(),
-            Ops::SHR(_, _) => todo!(),
-            Ops::SUBN(_, _) => todo!(),
-            Ops::SHL(_, _) => todo!(),
-            Ops::SNE(_, _) => todo!(),
-            Ops::JPV(_) => todo!(),
-            Ops::RND(_, _) => todo!(),
-            Ops::SKP(_) => todo!(),
-            Ops::SKNP(_) => todo!(),
-            Ops::LDDT(_) => todo!(),
-            Ops::LDK(_) => todo!(),
-            Ops::LDDTE(_) => todo!(),
-            Ops::LDST(_) => todo!(),
-            Ops::ADDI(_) => todo!(),
-            Ops::LDF(_) => todo!(),
-            Ops::LDB(_) => todo!(),
-            Ops::LDI(_) => todo!(),
-            Ops::LDVI(_) => todo!(),
+            Ops::Data(_) => return Err(ExecError::Unsupported(i)),
+            Ops::SI(_, _) => return Err(ExecError::Unsupported(i)),
+            Ops::SIN(_, _) => return Err(ExecError::Unsupported(i)),
+            Ops::SVI(_, _) => return Err(ExecError::Unsupported(i)),
+            Ops::SIV(_, _) => return Err(ExecError::Unsupported(i)),
+            Ops::ORV(_, _) => return Err(ExecError::Unsupported(i)),
+            Ops::ANDV(_, _) => return Err(ExecError::Unsupported(i)),
+            Ops::XORV(_, _) => return Err(ExecError::Unsupported(i)),
+            Ops::ADDVC(_, _) => return Err(ExecError::Unsupported(i)),
+            Ops::SUBVC(_, _) => return Err(ExecError::Unsupported(i)),
+            Ops::SHR(_, _) => return Err(ExecError::Unsupported(i)),
+            Ops::SUBN(_, _) => return Err(ExecError::Unsupported(i)),
+            Ops::SHL(_, _) => return Err(ExecError::Unsupported(i)),
+            Ops::SNE(_, _) => return Err(ExecError::Unsupported(i)),
+            Ops::JPV(_) => return Err(ExecError::Unsupported(i)),
+            Ops::RND(rx, kk) => {
+                self.registers[rx] = self.next_random() & kk;
+            }
+            Ops::SKP(rx) => {
+                if self.is_key_pressed(self.registers[rx]) {
+                    self.pc += 2;
+                }
+            }
+            Ops::SKNP(rx) => {
+                if !self.is_key_pressed(self.registers[rx]) {
+                    self.pc += 2;
+                }
+            }
+            Ops::LDDT(rx) => {
+                self.registers[rx] = self.delay_timer;
+            }
+            Ops::LDK(rx) => {
+                match self.keypad.iter().position(|pressed| *pressed) {
+                    Some(key) => self.registers[rx] = key as u8,
+                    // Execute this instruction again until a key is pressed
+                    None => return Ok(()),
+                }
+            }
+            Ops::LDDTE(rx) => {
+                self.delay_timer = self.registers[rx];
+            }
+            Ops::LDST(rx) => {
+                self.sound_timer = self.registers[rx];
+            }
+            Ops::ADDI(_) => return Err(ExecError::Unsupported(i)),
+            Ops::LDF(rx) => {
+                self.I = self.font.small_glyph_address(self.registers[rx]);
+            }
+            Ops::LDHF(rx) => {
+                self.I = self.font.large_glyph_address(self.registers[rx]);
+            }
+            Ops::AUDIO => {
+                let mut pattern = [0; AUDIO_PATTERN_SIZE];
+                for (i, byte) in pattern.iter_mut().enumerate() {
+                    *byte = *self.memory.get_u8(self.I as usize + i);
+                }
+                self.audio_pattern = Some(pattern);
+            }
+            Ops::PITCH(rx) => {
+                self.pitch = self.registers[rx];
+            }
+            Ops::LDB(_) => return Err(ExecError::Unsupported(i)),
+            Ops::LDI(_) => return Err(ExecError::Unsupported(i)),
+            Ops::LDVI(_) => return Err(ExecError::Unsupported(i)),
         }
 
         self.pc += 2;
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::AppState;
+    use crate::{
+        display::DirtyRect,
+        font::{FontConfig, FontError, FontSet},
+        golden,
+    };
+
+    use super::{AppState, ExecError, Ops, RomError, StateError, MAX_PROGRAM_SIZE, STACK_SIZE};
 
     #[test]
     fn test_app_state() {
@@ -172,14 +722,330 @@ mod tests {
 
     #[test]
     fn test_ibm_logo() {
+        let prg = include_bytes!("../../chip8-roms/roms/IBM Logo.ch8");
+        let appstate = golden::run_script(prg, 10, &[]);
+
+        golden::assert_golden(&appstate, "ibm_logo");
+    }
+
+    /// Draws the glyph of key 0 or 1 depending on whether key 1 is held down
+    #[test]
+    fn test_scripted_input() {
+        // 00E0 CLS
+        // 6101 LD V1, 0x01
+        // A050 LD I, 0x50
+        // E1A1 SKNP V1
+        // A055 LD I, 0x55
+        // D005 DRW V0, V0, 5
+        // 120C JP 0x20C
+        let prg = [
+            0x00, 0xE0, 0x61, 0x01, 0xA0, 0x50, 0xE1, 0xA1, 0xA0, 0x55, 0xD0, 0x05, 0x12, 0x0C,
+        ];
+
+        let released = golden::run_script(&prg, 2, &[]);
+        let pressed = golden::run_script(&prg, 2, &[golden::InputEvent::down(0, 0x1)]);
+
+        assert_ne!(released.frame_hash(), pressed.frame_hash());
+        assert_eq!(released.vram[0][0..4], [true, true, true, true]);
+        assert_eq!(pressed.vram[0][0..4], [false, false, true, false]);
+    }
+
+    #[test]
+    fn test_try_new() {
+        assert!(AppState::try_new(&[0x00, 0xE0]).is_ok());
+        assert!(AppState::try_new(&[0; MAX_PROGRAM_SIZE]).is_ok());
+        assert_eq!(Some(RomError::Empty), AppState::try_new(&[]).err());
+        assert_eq!(
+            Some(RomError::TooLarge(MAX_PROGRAM_SIZE + 1)),
+            AppState::try_new(&[0; MAX_PROGRAM_SIZE + 1]).err()
+        );
+    }
+
+    #[test]
+    fn test_current_op() {
+        let prg = [0x00, 0xE0, 0x12, 0x00];
+        let mut appstate = AppState::new(&prg);
+
+        assert_eq!(Ops::CLS, appstate.current_op());
+        appstate.step();
+        assert_eq!(Ops::JP(0x200), appstate.current_op());
+        assert_eq!(0x202, appstate.pc);
+    }
+
+    #[test]
+    fn test_save_state() {
         let prg = include_bytes!("../../chip8-roms/roms/IBM Logo.ch8");
         let mut appstate = AppState::new(prg);
+        appstate.run_frame(golden::CYCLES_PER_FRAME);
+        appstate.sound_timer = 7;
+
+        let state = appstate.save_state();
+        let (pc, hash) = (appstate.pc, appstate.frame_hash());
 
         let mut i = 0;
-        while i < 20 {
+        while i < 5 {
+            appstate.run_frame(golden::CYCLES_PER_FRAME);
+            i += 1;
+        }
+        assert_ne!(hash, appstate.frame_hash());
+
+        appstate.load_state(&state).unwrap();
+        assert_eq!(pc, appstate.pc);
+        assert_eq!(7, appstate.sound_timer);
+        assert_eq!(hash, appstate.frame_hash());
+        assert_eq!(Some(DirtyRect::FULL), appstate.take_dirty());
+        assert_eq!(state, appstate.save_state());
+
+        let mut newer = state;
+        newer[4] = 2;
+        assert_eq!(Err(StateError::UnsupportedVersion(2)), appstate.load_state(&newer));
+        assert_eq!(Err(StateError::Invalid), appstate.load_state(&state[1..]));
+        assert_eq!(Err(StateError::Invalid), appstate.load_state(&[0; super::SAVE_STATE_SIZE]));
+
+        // PC and I have to point into memory
+        for (offset, value) in [(5, [0xFF, 0xFF]), (5, [0x0F, 0xFF]), (7, [0x10, 0x00])] {
+            let mut broken = state;
+            broken[offset..offset + 2].copy_from_slice(&value);
+            assert_eq!(Err(StateError::Invalid), appstate.load_state(&broken));
+        }
+
+        // So do the return addresses on the stack
+        let mut broken = state;
+        broken[26..28].copy_from_slice(&[0xFF, 0xFF]);
+        assert_eq!(Err(StateError::Invalid), appstate.load_state(&broken));
+
+        assert_eq!(pc, appstate.pc);
+        appstate.step();
+    }
+
+    /// Draws the glyph for 0xA from a relocated VIP font
+    #[test]
+    fn test_font_location() {
+        // 600A LD V0, 0x0A
+        // F029 LD F, V0
+        // 6100 LD V1, 0x00
+        // D115 DRW V1, V1, 5
+        let prg = [0x60, 0x0A, 0xF0, 0x29, 0x61, 0x00, 0xD1, 0x15];
+        let mut appstate = AppState::with_font(&prg, FontConfig::new(FontSet::CosmacVip, 0x100));
+
+        appstate.run_frame(4);
+
+        assert_eq!(0x132, appstate.I);
+        assert_eq!(appstate.vram[0][0..4], [true, true, true, true]);
+        assert_eq!(appstate.vram[1][0..4], [true, false, false, true]);
+    }
+
+    #[test]
+    fn test_large_font() {
+        // 6002 LD V0, 0x02
+        // F030 LD HF, V0
+        let prg = [0x60, 0x02, 0xF0, 0x30];
+        let mut appstate = AppState::with_font(&prg, FontConfig::new(FontSet::Octo, 0x000));
+
+        appstate.run_frame(2);
+
+        assert_eq!(0x064, appstate.I);
+    }
+
+    #[test]
+    fn test_font_below_program() {
+        // F029 LD F, V0
+        let prg = [0xF0, 0x29];
+        let font = FontConfig::new(FontSet::CosmacVip, 0x1F0);
+        assert_eq!(
+            Err(FontError::OverlapsProgram { address: 0x1F0, end: 0x240 }),
+            AppState::try_with_font(&prg, font).map(|_| ())
+        );
+
+        // Callers that asked for it get the font moved down
+        let mut appstate = AppState::with_font(&prg, font.clamped());
+        appstate.step();
+
+        assert_eq!(0x1B0, appstate.I);
+        assert_eq!(0xF0, appstate.memory.as_bytes()[0x1B0]);
+        assert_eq!(0xF0, appstate.memory.as_bytes()[0x200]);
+    }
+
+    #[test]
+    fn test_random() {
+        // C0FF RND V0, 0xFF
+        // C10F RND V1, 0x0F
+        // 1204 JP 0x204
+        let prg = [0xC0, 0xFF, 0xC1, 0x0F, 0x12, 0x04];
+        let run = |seed| {
+            let mut appstate = AppState::new(&prg);
+            appstate.seed_random(seed);
+            appstate.run_frame(2);
+            assert!(appstate.is_halted());
+            *appstate.registers()
+        };
+
+        assert_eq!(run(1), run(1));
+        assert_ne!(run(1), run(2));
+        assert!(run(3)[1] <= 0x0F);
+    }
+
+    #[test]
+    fn test_subroutine() {
+        // 2206 CALL 0x206
+        // 6101 LD V1, 0x01
+        // 1204 JP 0x204
+        // 6001 LD V0, 0x01
+        // 00EE RET
+        let prg = [0x22, 0x06, 0x61, 0x01, 0x12, 0x04, 0x60, 0x01, 0x00, 0xEE];
+        let mut appstate = AppState::new(&prg);
+
+        let mut trace = [0; 4];
+        let mut n = 0;
+        appstate
+            .try_run_frame(4, |pc, _| {
+                trace[n] = pc;
+                n += 1;
+            })
+            .unwrap();
+        assert_eq!([0x200, 0x206, 0x208, 0x202], trace);
+        assert_eq!([1, 1], appstate.registers()[..2]);
+        assert_eq!(0, appstate.sp);
+
+        // 00EE RET
+        let mut appstate = AppState::new(&[0x00, 0xEE]);
+        assert_eq!(Err(ExecError::StackUnderflow), appstate.try_step());
+        assert_eq!(0x200, appstate.pc);
+
+        // 2200 CALL 0x200
+        let mut appstate = AppState::new(&[0x22, 0x00]);
+        assert_eq!(Err(ExecError::StackOverflow), appstate.try_run_frame(1000, |_, _| {}));
+        assert_eq!(STACK_SIZE, appstate.sp);
+    }
+
+    #[test]
+    fn test_unsupported() {
+        // 8124 ADD V1, V2
+        let mut appstate = AppState::new(&[0x81, 0x24]);
+        assert_eq!(Err(ExecError::Unsupported(Ops::ADDVC(1, 2))), appstate.try_step());
+        assert_eq!(0x200, appstate.pc);
+    }
+
+    #[test]
+    fn test_wait_for_key() {
+        // F30A LD V3, K
+        let prg = [0xF3, 0x0A];
+        let mut appstate = AppState::new(&prg);
+
+        appstate.run_frame(10);
+        assert_eq!(0x200, appstate.pc);
+
+        appstate.set_key(0xB, true);
+        appstate.step();
+        assert_eq!(0x202, appstate.pc);
+        assert_eq!(0xB, appstate.registers[3]);
+    }
+
+    #[test]
+    fn test_timers() {
+        // 6002 LD V0, 0x02
+        // F015 LD DT, V0
+        // F018 LD ST, V0
+        // F107 LD V1, DT
+        let prg = [0x60, 0x02, 0xF0, 0x15, 0xF0, 0x18, 0xF1, 0x07];
+        let mut appstate = AppState::new(&prg);
+
+        appstate.run_frame(3);
+        assert_eq!(1, appstate.delay_timer);
+        assert_eq!(1, appstate.sound_timer);
+
+        appstate.run_frame(1);
+        assert_eq!(0, appstate.delay_timer);
+        assert_eq!(0, appstate.sound_timer);
+        assert_eq!(1, appstate.registers[1]);
+    }
+
+    #[test]
+    fn test_audio_pattern() {
+        // A208 LD I, 0x208
+        // F002 AUDIO
+        // 6070 LD V0, 0x70
+        // F03A PITCH V0
+        let mut prg = [0u8; 8 + 16];
+        prg[..8].copy_from_slice(&[0xA2, 0x08, 0xF0, 0x02, 0x60, 0x70, 0xF0, 0x3A]);
+        prg[8] = 0xF0;
+        prg[23] = 0x0F;
+
+        let mut appstate = AppState::new(&prg);
+        assert_eq!(None, appstate.audio_pattern());
+        assert_eq!(64, appstate.pitch());
+
+        appstate.run_frame(4);
+
+        let pattern = appstate.audio_pattern().unwrap();
+        assert_eq!(0xF0, pattern[0]);
+        assert_eq!(0x0F, pattern[15]);
+        assert_eq!(0x70, appstate.pitch());
+    }
+
+    /// A pattern at the end of memory continues at the start
+    #[test]
+    fn test_audio_pattern_wraps() {
+        // AFF8 LD I, 0xFF8
+        // F002 AUDIO
+        let prg = [0xAF, 0xF8, 0xF0, 0x02];
+        let mut appstate = AppState::new(&prg);
+        appstate.memory.as_bytes_mut()[0xFFF] = 0xAA;
+
+        appstate.run_frame(2);
+
+        let pattern = appstate.audio_pattern().unwrap();
+        assert_eq!(0xAA, pattern[7]);
+        assert_eq!(appstate.memory.as_bytes()[0x000..0x008], pattern[8..]);
+    }
+
+    /// Two DRW in a row may only draw one sprite per frame with display wait enabled
+    #[test]
+    fn test_display_wait() {
+        // A050 LD I, 0x50
+        // D005 DRW V0, V0, 5
+        // D005 DRW V0, V0, 5
+        // 1206 JP 0x206
+        let prg = [0xA0, 0x50, 0xD0, 0x05, 0xD0, 0x05, 0x12, 0x06];
+        let mut appstate = AppState::new(&prg);
+        appstate.display_wait = true;
+
+        // The first DRW waits for the vertical blank
+        assert_eq!(2, appstate.run_frame(100));
+        assert_eq!(0x202, appstate.pc);
+        assert!(!appstate.vram[0][0]);
+        assert!(!appstate.is_waiting_for_vblank());
+
+        // The sprite is drawn, the second DRW blocks until the next frame
+        appstate.run_frame(100);
+        assert_eq!(0x204, appstate.pc);
+        assert!(appstate.vram[0][0]);
+
+        appstate.run_frame(100);
+        assert_eq!(0x206, appstate.pc);
+        assert!(!appstate.vram[0][0]);
+    }
+
+    #[test]
+    fn test_take_dirty() {
+        // CLS, LD V0 0x08, LD V1 0x04, LD I 0x50, DRW V0 V1 5
+        let prg = [0x00, 0xE0, 0x60, 0x08, 0x61, 0x04, 0xA0, 0x50, 0xD0, 0x15];
+        let mut appstate = AppState::new(&prg);
+
+        appstate.step();
+        assert_eq!(Some(DirtyRect::FULL), appstate.take_dirty());
+
+        let mut i = 0;
+        while i < 4 {
             appstate.step();
             i += 1;
         }
+
+        assert_eq!(
+            Some(DirtyRect { x: 8, y: 4, width: 8, height: 5 }),
+            appstate.take_dirty()
+        );
+        assert_eq!(None, appstate.take_dirty());
     }
 
 }
@@ -0,0 +1,274 @@
+use core::f32::consts::LN_2;
+
+use crate::app::AppState;
+
+/// Timers are ticked at 60 Hz, the host fills one frame of samples per tick
+pub const TICKS_PER_SECOND: u32 = 60;
+
+/// XO-CHIP audio patterns are 128 1-bit samples
+pub const AUDIO_PATTERN_SIZE: usize = 16;
+const AUDIO_PATTERN_BITS: f32 = (AUDIO_PATTERN_SIZE * 8) as f32;
+
+/// The pitch at which patterns are played back at 4000 bits per second
+pub const DEFAULT_PITCH: u8 = 64;
+
+pub type AudioPattern = [u8; AUDIO_PATTERN_SIZE];
+
+/// Returns the playback rate of an XO-CHIP pattern in bits per second,
+/// 4000*2^((pitch-64)/48)
+pub fn pattern_rate(pitch: u8) -> f32 {
+    4000.0 * exp2((pitch as f32 - DEFAULT_PITCH as f32) / 48.0)
+}
+
+/// 2^x for the small range of exponents needed by [pattern_rate], `core` has no `powf`
+fn exp2(x: f32) -> f32 {
+    let mut n = x as i32;
+    if (n as f32) > x {
+        n -= 1;
+    }
+
+    // Polynomial approximation of 2^f for 0 <= f < 1
+    let f = x - n as f32;
+    let mut value =
+        1.0 + f * (LN_2 + f * (0.240_226_5 + f * (0.055_504_1 + f * (0.009_618_1 + f * 0.001_333_3))));
+
+    while n > 0 {
+        value *= 2.0;
+        n -= 1;
+    }
+    while n < 0 {
+        value /= 2.0;
+        n += 1;
+    }
+
+    value
+}
+
+/// Time the volume needs to fade in or out, avoids clicks at the start and end of a beep
+const RAMP_SECONDS: f32 = 0.002;
+
+/// Generates the beep of the sound timer as PCM samples
+///
+/// The host calls one of the `fill` functions with the current sound timer value
+/// once per tick and copies the samples to its audio device. Once an XO-CHIP
+/// pattern is set it is played instead of the square wave.
+#[derive(Debug, Clone)]
+pub struct AudioGenerator {
+    pub sample_rate: u32,
+
+    /// Frequency of the square wave in Hz
+    pub frequency: f32,
+
+    /// Volume between 0.0 and 1.0
+    pub volume: f32,
+
+    /// Position inside the current period, between 0.0 and 1.0
+    phase: f32,
+
+    /// Current gain of the fade in/out envelope, between 0.0 and 1.0
+    envelope: f32,
+
+    pattern: Option<AudioPattern>,
+    pitch: u8,
+
+    /// Position inside the pattern in bits, between 0.0 and 128.0
+    pattern_position: f32,
+}
+
+impl AudioGenerator {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            frequency: 440.0,
+            volume: 0.25,
+            phase: 0.0,
+            envelope: 0.0,
+            pattern: None,
+            pitch: DEFAULT_PITCH,
+            pattern_position: 0.0,
+        }
+    }
+
+    /// Sets the XO-CHIP pattern and pitch, None plays the square wave
+    pub fn set_pattern(&mut self, pattern: Option<AudioPattern>, pitch: u8) {
+        self.pattern = pattern;
+        self.pitch = pitch;
+    }
+
+    /// Takes over the pattern and pitch of the emulator, has to be called before filling
+    /// the samples of a tick
+    pub fn update(&mut self, app: &AppState) {
+        self.set_pattern(app.audio_pattern().copied(), app.pitch());
+    }
+
+    /// Number of samples that cover one tick of the timers
+    pub fn samples_per_tick(&self) -> usize {
+        (self.sample_rate / TICKS_PER_SECOND) as usize
+    }
+
+    /// Returns the next sample between -1.0 and 1.0
+    fn next_sample(&mut self, active: bool) -> f32 {
+        let step = 1.0 / (RAMP_SECONDS * self.sample_rate as f32);
+        self.envelope = if active {
+            (self.envelope + step).min(1.0)
+        } else {
+            (self.envelope - step).max(0.0)
+        };
+
+        if self.envelope == 0.0 {
+            // Start the next beep at the beginning of a period
+            self.phase = 0.0;
+            self.pattern_position = 0.0;
+            return 0.0;
+        }
+
+        let value = match self.pattern {
+            Some(pattern) => self.next_pattern_sample(&pattern),
+            None => self.next_square_sample(),
+        };
+
+        value * self.volume * self.envelope
+    }
+
+    fn next_square_sample(&mut self) -> f32 {
+        let value = if self.phase < 0.5 { 1.0 } else { -1.0 };
+
+        self.phase += self.frequency / self.sample_rate as f32;
+        if self.phase >= 1.0 {
+            self.phase -= 1.0;
+        }
+
+        value
+    }
+
+    /// Plays the pattern MSB first, looping after 128 bits
+    fn next_pattern_sample(&mut self, pattern: &AudioPattern) -> f32 {
+        let bit = self.pattern_position as usize;
+        let value = if pattern[bit / 8] & (0x80 >> (bit % 8)) != 0 { 1.0 } else { -1.0 };
+
+        self.pattern_position += pattern_rate(self.pitch) / self.sample_rate as f32;
+        while self.pattern_position >= AUDIO_PATTERN_BITS {
+            self.pattern_position -= AUDIO_PATTERN_BITS;
+        }
+
+        value
+    }
+
+    /// Fills the buffer with samples between -1.0 and 1.0,
+    /// the beep is audible while the sound timer is not 0
+    pub fn fill_f32(&mut self, sound_timer: u8, out: &mut [f32]) {
+        for sample in out.iter_mut() {
+            *sample = self.next_sample(sound_timer > 0);
+        }
+    }
+
+    /// Fills the buffer with signed 16-bit samples,
+    /// the beep is audible while the sound timer is not 0
+    pub fn fill_i16(&mut self, sound_timer: u8, out: &mut [i16]) {
+        for sample in out.iter_mut() {
+            *sample = (self.next_sample(sound_timer > 0) * i16::MAX as f32) as i16;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{pattern_rate, AudioGenerator};
+
+    #[test]
+    fn rate() {
+        assert_eq!(4000.0, pattern_rate(64));
+        assert_eq!(8000.0, pattern_rate(112));
+        assert_eq!(2000.0, pattern_rate(16));
+        assert!((pattern_rate(88) - 5656.854).abs() < 0.05);
+        assert!((pattern_rate(0) - 1587.401).abs() < 0.05);
+    }
+
+    /// At 4000 Hz and the default pitch every sample plays one bit of the pattern
+    #[test]
+    fn pattern() {
+        let mut audio = AudioGenerator::new(4000);
+
+        let mut pattern = [0x00; 16];
+        pattern[0] = 0xF0;
+        pattern[1] = 0xAA;
+        audio.set_pattern(Some(pattern), 64);
+
+        let mut out = [0f32; 256];
+        audio.fill_f32(1, &mut out);
+
+        let bits: [bool; 16] = core::array::from_fn(|i| out[i] > 0.0);
+        assert_eq!(
+            [
+                true, true, true, true, false, false, false, false, true, false, true, false, true,
+                false, true, false
+            ],
+            bits
+        );
+
+        // The pattern loops
+        assert!(out[128] > 0.0 && out[132] < 0.0);
+    }
+
+    #[test]
+    fn silence() {
+        let mut audio = AudioGenerator::new(48000);
+        let mut out = [1i16; 800];
+        audio.fill_i16(0, &mut out);
+
+        assert!(out.iter().all(|s| *s == 0));
+    }
+
+    /// The square wave changes sign twice per period
+    #[test]
+    fn frequency() {
+        let mut audio = AudioGenerator::new(48000);
+        audio.frequency = 600.0;
+
+        let mut out = [0f32; 48000];
+        audio.fill_f32(1, &mut out);
+
+        let mut changes = 0;
+        let mut i = 1;
+        while i < out.len() {
+            if (out[i - 1] < 0.0) != (out[i] < 0.0) {
+                changes += 1;
+            }
+            i += 1;
+        }
+
+        assert_eq!(1199, changes);
+    }
+
+    #[test]
+    fn volume() {
+        let mut audio = AudioGenerator::new(48000);
+        audio.volume = 0.5;
+
+        let mut out = [0f32; 800];
+        audio.fill_f32(1, &mut out);
+
+        let peak = out.iter().fold(0f32, |p, s| p.max(s.abs()));
+        assert_eq!(0.5, peak);
+    }
+
+    /// Neither the start nor the end of the beep jumps to full volume
+    #[test]
+    fn click_free() {
+        let mut audio = AudioGenerator::new(48000);
+
+        let mut out = [0f32; 800];
+        audio.fill_f32(1, &mut out);
+        assert!(out[0].abs() < 0.01);
+
+        audio.fill_f32(0, &mut out);
+        assert!(out[0].abs() > 0.2);
+        assert!(out[1].abs() < out[0].abs());
+        assert_eq!(0.0, out[799]);
+    }
+
+    #[test]
+    fn samples_per_tick() {
+        assert_eq!(735, AudioGenerator::new(44100).samples_per_tick());
+    }
+}
@@ -0,0 +1,464 @@
+//! Plays a ROM in the terminal, e.g. over SSH
+//!
+//! Every character cell shows two pixels with the upper half block `▀`, the upper
+//! pixel as foreground and the lower one as background colour. Terminals only
+//! report key presses, so a key counts as held until no press arrived for `--hold`
+//! milliseconds; the auto repeat of the terminal keeps it down while held. The first
+//! press holds the key for `--delay` milliseconds, which has to cover the delay
+//! before the terminal starts repeating.
+mod common;
+
+use std::{
+    env,
+    fmt::Write as _,
+    io::{self, Read, Write},
+    panic,
+    process::{self, Command, Stdio},
+    sync::mpsc::{self, Receiver},
+    thread,
+    time::{Duration, Instant},
+};
+
+use common::{options_or_exit, read_rom_or_exit, unknown_option, Args};
+
+use chip8::{
+    app::{AppState, ExecError},
+    chip8::ch8_types::{DISPLAY_HEIGHT, KEYPAD_SIZE},
+    phosphor::{Intensity, Phosphor, PhosphorMode},
+    render::{Palette, Rgb},
+};
+
+const USAGE: &str = "\
+Usage: chip8-term [OPTIONS] <ROM>
+
+Options:
+  --cycles <N>       Instructions per frame [default: 10]
+  --palette <NAME>   classic, amber, green, octo or high-contrast [default: classic]
+  --hold <MS>        How long a key stays down after a repeated press [default: 150]
+  --delay <MS>       How long a key stays down after the first press [default: 600]
+  --phosphor <MODE>  Persistence of turned off pixels: off, decay or blend [default: off]
+  --seed <N>         Seed of RND
+  -h, --help         Print this help
+
+Keys:
+  1 2 3 4            1 2 3 C
+  Q W E R            4 5 6 D
+  A S D F     ->     7 8 9 E
+  Z X C V            A 0 B F
+
+  Space pauses, . steps one instruction while paused, P switches the phosphor mode,
+  Ctrl-C quits";
+
+const FRAME_TIME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+const CTRL_C: u8 = 0x03;
+const ESC: u8 = 0x1B;
+const BEL: char = '\x07';
+
+/// Keyboard keys of the hex keypad, in the usual layout on the left of a QWERTY keyboard
+const KEYMAP: [(u8, u8); KEYPAD_SIZE] = [
+    (b'1', 0x1),
+    (b'2', 0x2),
+    (b'3', 0x3),
+    (b'4', 0xC),
+    (b'q', 0x4),
+    (b'w', 0x5),
+    (b'e', 0x6),
+    (b'r', 0xD),
+    (b'a', 0x7),
+    (b's', 0x8),
+    (b'd', 0x9),
+    (b'f', 0xE),
+    (b'z', 0xA),
+    (b'x', 0x0),
+    (b'c', 0xB),
+    (b'v', 0xF),
+];
+
+fn key_for(byte: u8) -> Option<u8> {
+    let byte = byte.to_ascii_lowercase();
+    KEYMAP.iter().find(|(b, _)| *b == byte).map(|(_, key)| *key)
+}
+
+/// What a byte read from the terminal asks for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    Key(u8),
+    Pause,
+    Step,
+    Phosphor,
+    Quit,
+}
+
+/// Frames after which an unfinished escape sequence counts as a lone ESC
+const ESCAPE_TIMEOUT: u64 = 3;
+
+/// Turns the raw bytes from the terminal into actions
+#[derive(Debug, Default)]
+struct InputParser {
+    /// Inside an escape sequence, e.g. of the arrow keys, which is skipped
+    escape: bool,
+
+    /// Frame the escape sequence started in
+    escape_frame: u64,
+}
+
+impl InputParser {
+    fn feed(&mut self, byte: u8, frame: u64) -> Option<Action> {
+        // Sequences arrive in one go, a lone ESC must not swallow the next key
+        if self.escape && frame >= self.escape_frame + ESCAPE_TIMEOUT {
+            self.escape = false;
+        }
+
+        if self.escape {
+            // CSI and SS3 sequences end with a byte in 0x40 - 0x7E, the introducers are part of them
+            self.escape = !(0x40..=0x7E).contains(&byte) || byte == b'[' || byte == b'O';
+            return None;
+        }
+
+        match byte {
+            ESC => {
+                self.escape = true;
+                self.escape_frame = frame;
+                None
+            }
+            CTRL_C => Some(Action::Quit),
+            b' ' => Some(Action::Pause),
+            b'.' => Some(Action::Step),
+            b'p' | b'P' => Some(Action::Phosphor),
+            _ => key_for(byte).map(Action::Key),
+        }
+    }
+}
+
+/// Emulates key releases, a key is down for `first_hold` frames after a new press
+/// and for `hold` frames after every repeated one
+#[derive(Debug)]
+struct Keypad {
+    hold: u64,
+    first_hold: u64,
+    release_at: [u64; KEYPAD_SIZE],
+}
+
+impl Keypad {
+    fn new(hold: u64, first_hold: u64) -> Self {
+        Self {
+            hold,
+            first_hold,
+            release_at: [0; KEYPAD_SIZE],
+        }
+    }
+
+    fn press(&mut self, key: u8, frame: u64) {
+        let hold = if self.is_down(key, frame) { self.hold } else { self.first_hold };
+        self.release_at[key as usize] = frame + hold;
+    }
+
+    fn is_down(&self, key: u8, frame: u64) -> bool {
+        frame < self.release_at[key as usize]
+    }
+}
+
+/// Draws the frame from the top left corner of the terminal, two pixel rows per line
+fn render(frame: &Intensity, palette: &Palette, out: &mut String) {
+    out.push_str("\x1b[H");
+
+    for rows in frame.chunks_exact(2) {
+        let mut last = None;
+        for (top, bottom) in rows[0].iter().zip(rows[1].iter()) {
+            let (top, bottom) = (palette.shade(*top), palette.shade(*bottom));
+
+            if last != Some((top, bottom)) {
+                let ([fr, fg, fb], [br, bg, bb]): (Rgb, Rgb) = (top, bottom);
+                let _ = write!(out, "\x1b[38;2;{};{};{};48;2;{};{};{}m", fr, fg, fb, br, bg, bb);
+                last = Some((top, bottom));
+            }
+            out.push('▀');
+        }
+        // Raw mode doesn't return the carriage on a line feed
+        out.push_str("\x1b[0m\r\n");
+    }
+}
+
+fn status(app: &AppState, paused: bool, phosphor: PhosphorMode) -> String {
+    let state = if paused { "paused" } else { "running" };
+    let phosphor = PhosphorMode::PRESETS
+        .iter()
+        .find(|(_, mode)| *mode == phosphor)
+        .map_or("custom", |(name, _)| *name);
+    format!(
+        "\x1b[2K{:03X}  {:<16} I {:03X}  DT {:02X}  ST {:02X}  {}  phosphor {}\r\n",
+        app.pc,
+        app.current_op().to_string(),
+        app.I,
+        app.delay_timer,
+        app.sound_timer,
+        state,
+        phosphor
+    )
+}
+
+/// Puts the terminal into raw mode until it's dropped
+struct RawMode {
+    saved: String,
+}
+
+impl RawMode {
+    fn enable() -> io::Result<Self> {
+        let saved = stty(&["-g"])?;
+        stty(&["raw", "-echo"])?;
+
+        // Leave a usable terminal behind if the emulator panics
+        let restore = saved.clone();
+        let hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            let _ = stty(&[&restore]);
+            print!("\x1b[0m\x1b[?25h\r\n");
+            hook(info);
+        }));
+
+        print!("\x1b[2J\x1b[?25l");
+        Ok(Self { saved })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = stty(&[&self.saved]);
+        print!("\x1b[0m\x1b[?25h\r\n");
+        let _ = io::stdout().flush();
+    }
+}
+
+/// Runs `stty` on the terminal of stdin and returns its output
+fn stty(args: &[&str]) -> io::Result<String> {
+    let output = Command::new("stty").args(args).stdin(Stdio::inherit()).output()?;
+    if !output.status.success() {
+        return Err(io::Error::other("stty failed, is stdin a terminal?"));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Reads stdin on a separate thread so the frames don't wait for input
+fn spawn_reader() -> Receiver<u8> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for byte in io::stdin().lock().bytes() {
+            if byte.map(|b| sender.send(b)).map_or(true, |sent| sent.is_err()) {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+struct Options {
+    rom: String,
+    cycles: usize,
+    palette: Palette,
+    hold_millis: u64,
+    delay_millis: u64,
+    phosphor: PhosphorMode,
+    seed: Option<u32>,
+}
+
+impl Options {
+    /// Parses the arguments without the program name, `Ok(None)` asks for the help
+    fn parse(args: impl Iterator<Item = String>) -> Result<Option<Self>, String> {
+        let mut options = Options {
+            rom: String::new(),
+            cycles: 10,
+            palette: Palette::default(),
+            hold_millis: 150,
+            delay_millis: 600,
+            phosphor: PhosphorMode::Off,
+            seed: None,
+        };
+
+        let mut args = Args::new(args);
+        while let Some(option) = args.next_option()? {
+            match option.as_str() {
+                "-h" | "--help" => return Ok(None),
+                "--cycles" => options.cycles = args.number(&option)?,
+                "--palette" => options.palette = args.palette(&option)?,
+                "--hold" => options.hold_millis = args.number(&option)?,
+                "--delay" => options.delay_millis = args.number(&option)?,
+                "--phosphor" => {
+                    let name = args.value(&option)?;
+                    options.phosphor =
+                        PhosphorMode::from_name(&name).ok_or_else(|| format!("unknown phosphor mode {:?}", name))?;
+                }
+                "--seed" => options.seed = Some(args.number(&option)?),
+                _ => return Err(unknown_option(&option)),
+            }
+        }
+
+        options.rom = args.rom()?;
+        Ok(Some(options))
+    }
+}
+
+/// Turns an instruction that couldn't be executed into the error that ends the game
+fn exec_error(app: &AppState, e: ExecError) -> io::Error {
+    io::Error::other(format!("{} at 0x{:03X} ({})", e, app.pc, app.current_op()))
+}
+
+fn run(options: &Options, mut app: AppState) -> io::Result<()> {
+    let _raw = RawMode::enable()?;
+    let input = spawn_reader();
+    let mut stdout = io::stdout().lock();
+
+    let mut parser = InputParser::default();
+    let frames = |millis: u64| (millis * 60).div_ceil(1000).max(1);
+    let mut keypad = Keypad::new(frames(options.hold_millis), frames(options.delay_millis));
+    let mut paused = false;
+    let mut phosphor = Phosphor::new(options.phosphor);
+    let mut redraw = true;
+    let mut sounding = false;
+    let mut shown = String::new();
+    let mut out = String::new();
+
+    let mut next_frame = Instant::now();
+    for frame in 0.. {
+        while let Ok(byte) = input.try_recv() {
+            match parser.feed(byte, frame) {
+                Some(Action::Key(key)) => keypad.press(key, frame),
+                Some(Action::Pause) => paused = !paused,
+                Some(Action::Step) if paused => {
+                    app.try_step().map_err(|e| exec_error(&app, e))?;
+                }
+                Some(Action::Phosphor) => {
+                    phosphor.mode = phosphor.mode.next();
+                    redraw = true;
+                }
+                Some(Action::Quit) => return Ok(()),
+                _ => {}
+            }
+        }
+
+        for key in 0..KEYPAD_SIZE as u8 {
+            app.set_key(key, keypad.is_down(key, frame));
+        }
+        if !paused {
+            app.try_run_frame(options.cycles, |_, _| {}).map_err(|e| exec_error(&app, e))?;
+        }
+
+        // One bell per beep, the terminal decides what it sounds like
+        out.clear();
+        if app.is_sounding() && !sounding {
+            out.push(BEL);
+        }
+        sounding = app.is_sounding();
+
+        // Steps while paused change the VRAM too, fading pixels change without it
+        let dirty = app.take_dirty().is_some();
+        if !paused || dirty {
+            phosphor.update(&app.vram);
+        }
+        let fading = !paused && phosphor.mode != PhosphorMode::Off;
+        if dirty || fading || redraw {
+            render(phosphor.frame(), &options.palette, &mut out);
+            redraw = false;
+        }
+        let line = status(&app, paused, phosphor.mode);
+        if line != shown {
+            let _ = write!(out, "\x1b[{};1H{}", DISPLAY_HEIGHT / 2 + 1, line);
+            shown = line;
+        }
+        if !out.is_empty() {
+            stdout.write_all(out.as_bytes())?;
+            stdout.flush()?;
+        }
+
+        // Frames that ran late are not caught up
+        next_frame += FRAME_TIME;
+        let now = Instant::now();
+        if next_frame > now {
+            thread::sleep(next_frame - now);
+        } else {
+            next_frame = now;
+        }
+    }
+    Ok(())
+}
+
+fn main() {
+    let options = options_or_exit(Options::parse(env::args().skip(1)), USAGE);
+    let rom = read_rom_or_exit(&options.rom);
+
+    let mut app = AppState::try_new(&rom).unwrap();
+    if let Some(seed) = options.seed {
+        app.seed_random(seed);
+    }
+
+    if let Err(e) = run(&options, app) {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chip8::{
+        chip8::ch8_types::{DISPLAY_HEIGHT, DISPLAY_WIDTH},
+        render::{intensity_from_vram, Palette},
+    };
+
+    use super::{render, Action, InputParser, Keypad};
+
+    #[test]
+    fn input() {
+        let mut parser = InputParser::default();
+        let actions: Vec<_> = b"wX \x1b[A.\x1bOBp\x03".iter().filter_map(|b| parser.feed(*b, 0)).collect();
+
+        // The arrow keys don't press A and B
+        assert_eq!(
+            vec![Action::Key(0x5), Action::Key(0x0), Action::Pause, Action::Step, Action::Phosphor, Action::Quit],
+            actions
+        );
+    }
+
+    #[test]
+    fn lone_escape() {
+        let mut parser = InputParser::default();
+        assert_eq!(None, parser.feed(0x1B, 10));
+        assert_eq!(None, parser.feed(b'w', 11));
+
+        assert_eq!(None, parser.feed(0x1B, 20));
+        assert_eq!(Some(Action::Key(0x5)), parser.feed(b'w', 23));
+    }
+
+    #[test]
+    fn key_release() {
+        let mut keypad = Keypad::new(3, 30);
+        keypad.press(0xA, 10);
+        assert!(keypad.is_down(0xA, 39));
+        assert!(!keypad.is_down(0xA, 40));
+        assert!(!keypad.is_down(0xB, 10));
+
+        // Auto repeat keeps it down for the short hold
+        keypad.press(0xA, 35);
+        assert!(keypad.is_down(0xA, 37));
+        assert!(!keypad.is_down(0xA, 38));
+
+        // A press after the release waits for the repeat delay again
+        keypad.press(0xA, 50);
+        assert!(keypad.is_down(0xA, 79));
+    }
+
+    #[test]
+    fn half_blocks() {
+        let mut vram = [[false; DISPLAY_WIDTH]; DISPLAY_HEIGHT];
+        vram[0][0] = true;
+        vram[1][1] = true;
+
+        let mut out = String::new();
+        render(&intensity_from_vram(&vram), &Palette::CLASSIC, &mut out);
+
+        assert_eq!(DISPLAY_HEIGHT / 2, out.matches("\r\n").count());
+        assert_eq!(DISPLAY_WIDTH * DISPLAY_HEIGHT / 2, out.matches('▀').count());
+        assert!(out.starts_with(
+            "\x1b[H\x1b[38;2;255;255;255;48;2;0;0;0m▀\x1b[38;2;0;0;0;48;2;255;255;255m▀\x1b[38;2;0;0;0;48;2;0;0;0m▀▀"
+        ));
+    }
+}
@@ -0,0 +1,402 @@
+//! Runs a ROM headless and prints what it left behind, for scripted testing
+//!
+//! ```text
+//! chip8 --frames 120 --input 5@10-20 --screen pbm roms/Pong.ch8 > pong.pbm
+//! ```
+mod common;
+
+use std::{
+    env, fmt, fs,
+    io::{self, Write},
+    process,
+};
+
+use common::{options_or_exit, read_rom_or_exit, unknown_option, Args};
+
+use chip8::{
+    app::{AppState, ExecError},
+    display::write_pbm,
+    font::{FontConfig, FontSet},
+    render::{intensity_from_vram, render, write_ppm, Filter, Palette},
+    wav::AudioCapture,
+};
+
+const USAGE: &str = "\
+Usage: chip8 [OPTIONS] <ROM>
+
+Options:
+  --frames <N>         Frames to run at most [default: 600]
+  --until-halt         Stop once the program jumps to itself
+  --quirks <PROFILE>   vip, schip or octo [default: schip]
+  --cycles <N>         Instructions per frame [default: 10]
+  --seed <N>           Seed of RND
+  --input <EVENTS>     Comma separated KEY@FRAME or KEY@FROM-TO, e.g. 5@10-20,A@30
+  --screen <FORMAT>    Print the final screen as text, pbm or ppm [default: text]
+  --palette <NAME>     Colours of the ppm screen: classic, amber, green, octo or high-contrast [default: classic]
+  --filter <NAME>      Upscaling of the ppm screen: none, scale2x, scale3x, hq2x or scanlines [default: none]
+  --no-screen          Don't print the screen
+  --registers          Print the registers and timers
+  --trace              Print every executed instruction to stderr
+  --audio <FILE>       Write the sound of the run to a WAV file
+  -h, --help           Print this help";
+
+const SAMPLE_RATE: u32 = 44_100;
+
+/// Interpreter behaviours selected with `--quirks`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Quirks {
+    /// COSMAC VIP font, `DRW` waits for the vertical blank
+    Vip,
+    Schip,
+    /// Sprites wrap around the edges of the display
+    Octo,
+}
+
+impl Quirks {
+    fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "vip" => Ok(Quirks::Vip),
+            "schip" => Ok(Quirks::Schip),
+            "octo" => Ok(Quirks::Octo),
+            _ => Err(format!("unknown quirks profile {:?}", name)),
+        }
+    }
+
+    fn create(&self, rom: &[u8]) -> AppState {
+        let set = match self {
+            Quirks::Vip => FontSet::CosmacVip,
+            Quirks::Schip => FontSet::Schip,
+            Quirks::Octo => FontSet::Octo,
+        };
+        let mut app = AppState::with_font(rom, FontConfig { set, ..FontConfig::default() });
+        app.display_wait = *self == Quirks::Vip;
+        app.wrap_sprites = *self == Quirks::Octo;
+        app
+    }
+}
+
+/// Holds a key down for the frames `from..to`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct KeyPress {
+    key: u8,
+    from: usize,
+    to: usize,
+}
+
+impl KeyPress {
+    fn parse(event: &str) -> Result<Self, String> {
+        let error = || format!("invalid input event {:?}, expected KEY@FRAME or KEY@FROM-TO", event);
+
+        let (key, frames) = event.split_once('@').ok_or_else(error)?;
+        let key = u8::from_str_radix(key, 16).ok().filter(|k| *k < 16).ok_or_else(error)?;
+        let (from, to) = match frames.split_once('-') {
+            Some((from, to)) => (from.parse().map_err(|_| error())?, to.parse().map_err(|_| error())?),
+            None => {
+                let frame: usize = frames.parse().map_err(|_| error())?;
+                (frame, frame.checked_add(1).ok_or_else(error)?)
+            }
+        };
+        Ok(Self { key, from, to })
+    }
+
+    fn is_down(&self, frame: usize) -> bool {
+        (self.from..self.to).contains(&frame)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ScreenFormat {
+    Text,
+    Pbm,
+    /// Rendered through [render] with the upscaling filter
+    Ppm,
+}
+
+#[derive(Debug)]
+struct Options {
+    rom: String,
+    frames: usize,
+    until_halt: bool,
+    quirks: Quirks,
+    cycles: usize,
+    seed: Option<u32>,
+    input: Vec<KeyPress>,
+    screen: Option<ScreenFormat>,
+    palette: Palette,
+    filter: Filter,
+    registers: bool,
+    trace: bool,
+    audio: Option<String>,
+}
+
+impl Options {
+    /// Parses the arguments without the program name, `Ok(None)` asks for the help
+    fn parse(args: impl Iterator<Item = String>) -> Result<Option<Self>, String> {
+        let mut options = Options {
+            rom: String::new(),
+            frames: 600,
+            until_halt: false,
+            quirks: Quirks::Schip,
+            cycles: 10,
+            seed: None,
+            input: Vec::new(),
+            screen: Some(ScreenFormat::Text),
+            palette: Palette::default(),
+            filter: Filter::Nearest(1),
+            registers: false,
+            trace: false,
+            audio: None,
+        };
+
+        let mut args = Args::new(args);
+        while let Some(option) = args.next_option()? {
+            match option.as_str() {
+                "-h" | "--help" => return Ok(None),
+                "--frames" => options.frames = args.number(&option)?,
+                "--until-halt" => options.until_halt = true,
+                "--quirks" => options.quirks = Quirks::parse(&args.value(&option)?)?,
+                "--cycles" => options.cycles = args.number(&option)?,
+                "--seed" => options.seed = Some(args.number(&option)?),
+                "--input" => {
+                    for event in args.value(&option)?.split(',').filter(|e| !e.is_empty()) {
+                        options.input.push(KeyPress::parse(event)?);
+                    }
+                }
+                "--screen" => {
+                    options.screen = match args.value(&option)?.as_str() {
+                        "text" => Some(ScreenFormat::Text),
+                        "pbm" => Some(ScreenFormat::Pbm),
+                        "ppm" => Some(ScreenFormat::Ppm),
+                        format => return Err(format!("unknown screen format {:?}", format)),
+                    }
+                }
+                "--palette" => options.palette = args.palette(&option)?,
+                "--filter" => {
+                    let name = args.value(&option)?;
+                    options.filter = Filter::from_name(&name).ok_or_else(|| format!("unknown filter {:?}", name))?;
+                }
+                "--no-screen" => options.screen = None,
+                "--registers" => options.registers = true,
+                "--trace" => options.trace = true,
+                "--audio" => options.audio = Some(args.value(&option)?),
+                _ => return Err(unknown_option(&option)),
+            }
+        }
+
+        options.rom = args.rom()?;
+        Ok(Some(options))
+    }
+}
+
+/// Prints the screen with `#` for set pixels
+fn write_text(app: &AppState, w: &mut impl fmt::Write) -> fmt::Result {
+    for row in app.vram.iter() {
+        for pixel in row.iter() {
+            w.write_char(if *pixel { '#' } else { '.' })?;
+        }
+        w.write_char('\n')?;
+    }
+    Ok(())
+}
+
+/// Prints the screen as a colour image, upscaled by the filter
+fn write_image(app: &AppState, filter: Filter, palette: &Palette, w: &mut impl fmt::Write) -> fmt::Result {
+    let (width, height) = filter.output_size();
+    let mut rgba = vec![0; filter.buffer_len()];
+    render(&intensity_from_vram(&app.vram), filter, palette, &mut rgba);
+    write_ppm(&rgba, width, height, w)
+}
+
+fn write_registers(app: &AppState, w: &mut impl fmt::Write) -> fmt::Result {
+    for (i, v) in app.registers().iter().enumerate() {
+        let separator = if i % 8 == 7 { '\n' } else { ' ' };
+        write!(w, "V{:X}={:02X}{}", i, v, separator)?;
+    }
+    writeln!(w, "PC={:03X} I={:03X} SP={} DT={:02X} ST={:02X}", app.pc, app.I, app.sp, app.delay_timer, app.sound_timer)?;
+
+    write!(w, "Stack")?;
+    for address in app.stack().iter().rev() {
+        write!(w, " {:03X}", address)?;
+    }
+    writeln!(w)
+}
+
+/// How a run ended, `error` is set when an instruction couldn't be executed
+struct Run {
+    app: AppState,
+    audio: Option<AudioCapture>,
+    error: Option<ExecError>,
+}
+
+/// Runs the ROM one frame at a time, the trace goes to `trace`
+fn run(options: &Options, rom: &[u8], trace: &mut impl Write) -> io::Result<Run> {
+    let mut app = options.quirks.create(rom);
+    if let Some(seed) = options.seed {
+        app.seed_random(seed);
+    }
+    let mut audio = options.audio.as_ref().map(|_| AudioCapture::new(SAMPLE_RATE));
+
+    for frame in 0..options.frames {
+        if options.until_halt && app.is_halted() {
+            break;
+        }
+        for key in 0..16 {
+            app.set_key(key, options.input.iter().any(|p| p.key == key && p.is_down(frame)));
+        }
+
+        let mut written = Ok(());
+        let result = app.try_run_frame(options.cycles, |pc, op| {
+            if options.trace && written.is_ok() {
+                written = writeln!(trace, "{:>5} {:03X}  {}", frame, pc, op);
+            }
+        });
+        written?;
+        if let Err(e) = result {
+            return Ok(Run { app, audio, error: Some(e) });
+        }
+
+        if let Some(audio) = &mut audio {
+            audio.capture_tick(&app);
+        }
+    }
+
+    Ok(Run { app, audio, error: None })
+}
+
+fn main() {
+    let options = options_or_exit(Options::parse(env::args().skip(1)), USAGE);
+    let rom = read_rom_or_exit(&options.rom);
+
+    let mut trace = io::BufWriter::new(io::stderr().lock());
+    let run = run(&options, &rom, &mut trace).and_then(|run| trace.flush().map(|_| run));
+    drop(trace);
+    let Run { app, audio, error } = match run {
+        Ok(run) => run,
+        Err(e) => {
+            let _ = writeln!(io::stderr(), "error: could not write the trace: {}", e);
+            process::exit(1);
+        }
+    };
+
+    let mut out = String::new();
+    match options.screen {
+        Some(ScreenFormat::Text) => write_text(&app, &mut out).unwrap(),
+        Some(ScreenFormat::Pbm) => write_pbm(&app.vram, &mut out).unwrap(),
+        Some(ScreenFormat::Ppm) => write_image(&app, options.filter, &options.palette, &mut out).unwrap(),
+        None => {}
+    }
+    if options.registers {
+        write_registers(&app, &mut out).unwrap();
+    }
+    print!("{}", out);
+
+    if let (Some(path), Some(audio)) = (&options.audio, audio) {
+        let written = fs::File::create(path).and_then(|file| audio.write_wav(&mut io::BufWriter::new(file)));
+        if let Err(e) = written {
+            eprintln!("error: could not write {}: {}", path, e);
+            process::exit(1);
+        }
+    }
+
+    if let Some(e) = error {
+        eprintln!("error: {} at 0x{:03X} ({})", e, app.pc, app.current_op());
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chip8::render::{Filter, Palette};
+
+    use super::{run, KeyPress, Options, Quirks, ScreenFormat};
+
+    fn parse(args: &str) -> Result<Option<Options>, String> {
+        Options::parse(args.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn arguments() {
+        let options = parse("--frames 30 --quirks vip --seed 7 --screen pbm --input 5@10-20,a@3 pong.ch8")
+            .unwrap()
+            .unwrap();
+        assert_eq!("pong.ch8", options.rom);
+        assert_eq!(30, options.frames);
+        assert_eq!(Quirks::Vip, options.quirks);
+        assert_eq!(Some(7), options.seed);
+        assert_eq!(Some(ScreenFormat::Pbm), options.screen);
+        assert_eq!(
+            vec![KeyPress { key: 5, from: 10, to: 20 }, KeyPress { key: 0xA, from: 3, to: 4 }],
+            options.input
+        );
+
+        assert!(parse("--help").unwrap().is_none());
+        assert!(parse("--frames").is_err());
+        assert!(parse("--quirks cosmac rom.ch8").is_err());
+
+        let options = parse("--screen ppm --palette amber --filter hq2x rom.ch8").unwrap().unwrap();
+        assert_eq!(Some(ScreenFormat::Ppm), options.screen);
+        assert_eq!(Palette::AMBER, options.palette);
+        assert_eq!(Filter::Hq2x, options.filter);
+        assert!(parse("--filter hq9x rom.ch8").is_err());
+        assert!(parse("--palette mauve rom.ch8").is_err());
+        assert!(parse("--frames 10").is_err());
+    }
+
+    #[test]
+    fn input_events() {
+        let press = KeyPress::parse("F@2-4").unwrap();
+        assert!(!press.is_down(1));
+        assert!(press.is_down(2));
+        assert!(press.is_down(3));
+        assert!(!press.is_down(4));
+
+        assert!(KeyPress::parse("10@2").is_err());
+        assert!(KeyPress::parse("5").is_err());
+        assert!(KeyPress::parse("5@x").is_err());
+        assert!(KeyPress::parse(&format!("5@{}", usize::MAX)).is_err());
+    }
+
+    /// Runs the ROM for a few frames and returns the registers and the screen
+    fn run_rom(args: &str, rom: &[u8]) -> ([u8; 16], Vec<String>) {
+        let options = parse(&format!("--frames 2 {} rom.ch8", args)).unwrap().unwrap();
+        let mut trace = Vec::new();
+        let run = run(&options, rom, &mut trace).unwrap();
+        assert!(run.error.is_none());
+
+        let mut screen = String::new();
+        super::write_text(&run.app, &mut screen).unwrap();
+        (*run.app.registers(), screen.lines().map(String::from).collect())
+    }
+
+    #[test]
+    fn add_wraps() {
+        // 60FF LD V0, 0xFF
+        // 7002 ADD V0, 0x02
+        // 1204 JP 0x204
+        let (registers, _) = run_rom("", &[0x60, 0xFF, 0x70, 0x02, 0x12, 0x04]);
+        assert_eq!(0x01, registers[0]);
+        assert_eq!(0x00, registers[0xF]);
+    }
+
+    /// The 0 glyph drawn across the bottom right corner
+    #[test]
+    fn sprites_at_the_edges() {
+        // 603E LD V0, 0x3E
+        // 611E LD V1, 0x1E
+        // A050 LD I, 0x050
+        // D015 DRW V0, V1, 5
+        // 1208 JP 0x208
+        let rom = [0x60, 0x3E, 0x61, 0x1E, 0xA0, 0x50, 0xD0, 0x15, 0x12, 0x08];
+
+        let (_, screen) = run_rom("--quirks schip", &rom);
+        assert_eq!("##", &screen[30][62..]);
+        assert_eq!("#.", &screen[31][62..]);
+        assert_eq!("................", &screen[0][..16]);
+
+        let (_, screen) = run_rom("--quirks octo", &rom);
+        assert_eq!("##", &screen[30][62..]);
+        assert_eq!("##..", &screen[30][..4]);
+        assert_eq!("#.", &screen[0][62..]);
+        assert_eq!(".#..", &screen[0][..4]);
+    }
+}
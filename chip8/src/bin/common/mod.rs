@@ -0,0 +1,82 @@
+//! Argument handling and ROM loading shared by the binaries
+use std::{fs, process, str::FromStr};
+
+use chip8::{app::AppState, render::Palette};
+
+/// Walks the command line, the first argument that isn't an option is the ROM path
+pub struct Args<I> {
+    args: I,
+    rom: Option<String>,
+}
+
+impl<I: Iterator<Item = String>> Args<I> {
+    /// Takes the arguments without the program name
+    pub fn new(args: I) -> Self {
+        Self { args, rom: None }
+    }
+
+    /// Returns the next option, options are checked by the caller
+    pub fn next_option(&mut self) -> Result<Option<String>, String> {
+        for arg in self.args.by_ref() {
+            if arg.starts_with('-') {
+                return Ok(Some(arg));
+            }
+            if self.rom.replace(arg.clone()).is_some() {
+                return Err(format!("unexpected argument {}", arg));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns the value following the option
+    pub fn value(&mut self, option: &str) -> Result<String, String> {
+        self.args.next().ok_or_else(|| format!("{} needs a value", option))
+    }
+
+    pub fn number<T: FromStr>(&mut self, option: &str) -> Result<T, String> {
+        let value = self.value(option)?;
+        value.parse().map_err(|_| format!("{:?} is not a valid number", value))
+    }
+
+    /// Returns the palette preset named by the value
+    pub fn palette(&mut self, option: &str) -> Result<Palette, String> {
+        let name = self.value(option)?;
+        Palette::from_name(&name).ok_or_else(|| format!("unknown palette {:?}", name))
+    }
+
+    pub fn rom(self) -> Result<String, String> {
+        self.rom.ok_or_else(|| "no ROM given".to_string())
+    }
+}
+
+pub fn unknown_option(option: &str) -> String {
+    format!("unknown option {}", option)
+}
+
+/// Returns the parsed options, prints the usage and exits if they were rejected or `None` asked for the help
+pub fn options_or_exit<T>(options: Result<Option<T>, String>, usage: &str) -> T {
+    match options {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", usage);
+            process::exit(0);
+        }
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, usage);
+            process::exit(2);
+        }
+    }
+}
+
+/// Reads the ROM file, exits if it can't be read or doesn't fit into memory
+pub fn read_rom_or_exit(path: &str) -> Vec<u8> {
+    let rom = fs::read(path).unwrap_or_else(|e| {
+        eprintln!("error: could not read {}: {}", path, e);
+        process::exit(1);
+    });
+    if let Err(e) = AppState::try_new(&rom) {
+        eprintln!("error: could not load {}: {}", path, e);
+        process::exit(1);
+    }
+    rom
+}
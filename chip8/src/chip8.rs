@@ -1,3 +1,5 @@
+use core::fmt;
+
 use ch8_types::{decode, decode_memory_address};
 
 pub mod ch8_types {
@@ -7,6 +9,9 @@ pub mod ch8_types {
     /// Should be not < 48
     pub const STACK_SIZE: usize = 0xFF;
 
+    /// Number of keys on the hex keypad
+    pub const KEYPAD_SIZE: usize = 16;
+
     pub const DISPLAY_HEIGHT: usize = 32;
     pub const DISPLAY_WIDTH: usize = 64;
 
@@ -30,6 +35,7 @@ pub mod ch8_types {
     pub type Registers = [Byte; REGISTER_SIZE];
     pub type Memory = [Byte; MEMORY_SIZE];
     pub type Stack = [MemoryAddress; STACK_SIZE];
+    pub type Keypad = [bool; KEYPAD_SIZE];
 
     pub fn decode(i: u16, mask: u16) -> u16 {
         i & mask
@@ -246,6 +252,12 @@ pub enum Ops {
     /// The value of I is set to the location for the hexadecimal sprite corresponding to the value of Vx. See section 2.4, Display, for more information on the Chip-8 hexadecimal font.
     LDF(ch8_types::RegisterIndex),
 
+    /// Fx30 - LD HF, Vx (SUPER-CHIP)
+    /// Set I = location of large sprite for digit Vx.
+    ///
+    /// The value of I is set to the location of the 10-byte high resolution sprite corresponding to the value of Vx.
+    LDHF(ch8_types::RegisterIndex),
+
     /// Fx33 - LD B, Vx
     /// Store BCD representation of Vx in memory locations I, I+1, and I+2.
     ///
@@ -263,6 +275,18 @@ pub enum Ops {
     ///
     /// The interpreter reads values from memory starting at location I into registers V0 through Vx.
     LDVI(ch8_types::RegisterIndex),
+
+    /// F002 - AUDIO (XO-CHIP)
+    /// Load the audio pattern buffer.
+    ///
+    /// The interpreter copies 16 bytes starting at location I into the audio pattern buffer, which is played back as 1-bit samples while the sound timer is active.
+    AUDIO,
+
+    /// Fx3A - PITCH Vx (XO-CHIP)
+    /// Set the playback rate of the audio pattern.
+    ///
+    /// The pattern is played back at 4000*2^((Vx-64)/48) bits per second.
+    PITCH(ch8_types::RegisterIndex),
     
     Data(u16),
 }
@@ -351,9 +375,7 @@ impl From<[u8; 2]> for Ops {
                             0xE => {
                                 Self::SHL(x, y)
                             }
-                            _ => {
-                                panic!("Opcode not defined: {}", instr)
-                            }
+                            _ => Self::Data(value),
                         }
                     }
                     0x9 => {
@@ -379,14 +401,15 @@ impl From<[u8; 2]> for Ops {
                             0xA1 => {
                                 Self::SKNP(x)
                             }
-                            _ => {
-                                panic!("Unknown Opcode")
-                            }
+                            _ => Self::Data(value),
                         }
                     }
                     0xF => {
                         let x = (decode(value, 0xF00) as ch8_types::RegisterIndex) >> 8;
                         match decode(value, 0xFF) as ch8_types::Byte {
+                            0x02 if x == 0 => {
+                                Self::AUDIO
+                            }
                             0x07 => {
                                 Self::LDDT(x)
                             }
@@ -405,27 +428,79 @@ impl From<[u8; 2]> for Ops {
                             0x29 => {
                                 Self::LDF(x)
                             }
+                            0x30 => {
+                                Self::LDHF(x)
+                            }
                             0x33 => {
                                 Self::LDB(x)
                             }
+                            0x3A => {
+                                Self::PITCH(x)
+                            }
                             0x55 => {
                                 Self::LDI(x)
                             }
                             0x65 => {
                                 Self::LDVI(x)
                             }
-                            _ => {
-                                panic!("Opcode not found: {}", instr)
-                            }
+                            _ => Self::Data(value),
                         }
                     }
-                    _ => todo!("Opcode not defined: {}", instr),
+                    // 0nnn - SYS addr is not supported
+                    _ => Self::Data(value),
                 }
             }
         }
     }
 }
 
+/// Formats the instruction in the assembly syntax of Cowgod's reference,
+/// e.g. `DRW V0, V1, 5` or `LD I, 0x22A`
+impl fmt::Display for Ops {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Ops::CLS => write!(f, "CLS"),
+            Ops::RET => write!(f, "RET"),
+            Ops::JP(addr) => write!(f, "JP 0x{:03X}", addr),
+            Ops::CALL(addr) => write!(f, "CALL 0x{:03X}", addr),
+            Ops::DRW(x, y, n) => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            Ops::LD_V(x, kk) => write!(f, "LD V{:X}, 0x{:02X}", x, kk),
+            Ops::ADD_V(x, kk) => write!(f, "ADD V{:X}, 0x{:02X}", x, kk),
+            Ops::SET_I(addr) => write!(f, "LD I, 0x{:03X}", addr),
+            Ops::SI(x, kk) => write!(f, "SE V{:X}, 0x{:02X}", x, kk),
+            Ops::SIN(x, kk) => write!(f, "SNE V{:X}, 0x{:02X}", x, kk),
+            Ops::SVI(x, y) => write!(f, "SE V{:X}, V{:X}", x, y),
+            Ops::SIV(x, y) => write!(f, "LD V{:X}, V{:X}", x, y),
+            Ops::ORV(x, y) => write!(f, "OR V{:X}, V{:X}", x, y),
+            Ops::ANDV(x, y) => write!(f, "AND V{:X}, V{:X}", x, y),
+            Ops::XORV(x, y) => write!(f, "XOR V{:X}, V{:X}", x, y),
+            Ops::ADDVC(x, y) => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Ops::SUBVC(x, y) => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Ops::SHR(x, y) => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Ops::SUBN(x, y) => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Ops::SHL(x, y) => write!(f, "SHL V{:X}, V{:X}", x, y),
+            Ops::SNE(x, y) => write!(f, "SNE V{:X}, V{:X}", x, y),
+            Ops::JPV(addr) => write!(f, "JP V0, 0x{:03X}", addr),
+            Ops::RND(x, kk) => write!(f, "RND V{:X}, 0x{:02X}", x, kk),
+            Ops::SKP(x) => write!(f, "SKP V{:X}", x),
+            Ops::SKNP(x) => write!(f, "SKNP V{:X}", x),
+            Ops::LDDT(x) => write!(f, "LD V{:X}, DT", x),
+            Ops::LDK(x) => write!(f, "LD V{:X}, K", x),
+            Ops::LDDTE(x) => write!(f, "LD DT, V{:X}", x),
+            Ops::LDST(x) => write!(f, "LD ST, V{:X}", x),
+            Ops::ADDI(x) => write!(f, "ADD I, V{:X}", x),
+            Ops::LDF(x) => write!(f, "LD F, V{:X}", x),
+            Ops::LDHF(x) => write!(f, "LD HF, V{:X}", x),
+            Ops::LDB(x) => write!(f, "LD B, V{:X}", x),
+            Ops::LDI(x) => write!(f, "LD [I], V{:X}", x),
+            Ops::LDVI(x) => write!(f, "LD V{:X}, [I]", x),
+            Ops::AUDIO => write!(f, "AUDIO"),
+            Ops::PITCH(x) => write!(f, "PITCH V{:X}", x),
+            Ops::Data(data) => write!(f, "DW 0x{:04X}", data),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -663,6 +738,14 @@ mod tests {
         assert_eq!(Ops::LDF(1), instr);
     }
 
+    #[test]
+    fn ldhf() {
+        let opcode = [0xF1, 0x30];
+        let instr: Ops = opcode.into();
+
+        assert_eq!(Ops::LDHF(1), instr);
+    }
+
     #[test]
     fn ldb() {
         let opcode = [0xF1, 0x33];
@@ -679,6 +762,22 @@ mod tests {
         assert_eq!(Ops::LDI(1), instr);
     }
 
+    #[test]
+    fn audio() {
+        let opcode = [0xF0, 0x02];
+        let instr: Ops = opcode.into();
+
+        assert_eq!(Ops::AUDIO, instr);
+    }
+
+    #[test]
+    fn pitch() {
+        let opcode = [0xF1, 0x3A];
+        let instr: Ops = opcode.into();
+
+        assert_eq!(Ops::PITCH(1), instr);
+    }
+
     #[test]
     fn ldvi() {
         let opcode = [0xF1, 0x65];
@@ -686,4 +785,25 @@ mod tests {
 
         assert_eq!(Ops::LDVI(1), instr);
     }
+
+    #[test]
+    fn unknown_opcodes_are_data() {
+        assert_eq!(Ops::Data(0x8AAF), [0x8A, 0xAF].into());
+        assert_eq!(Ops::Data(0xE1FF), [0xE1, 0xFF].into());
+        assert_eq!(Ops::Data(0xF1FF), [0xF1, 0xFF].into());
+        assert_eq!(Ops::Data(0x0123), [0x01, 0x23].into());
+    }
+
+    #[test]
+    fn mnemonics() {
+        extern crate std;
+        use std::string::ToString;
+
+        assert_eq!("CLS", Ops::CLS.to_string());
+        assert_eq!("JP 0x228", Ops::JP(0x228).to_string());
+        assert_eq!("DRW V0, VA, 5", Ops::DRW(0x0, 0xA, 5).to_string());
+        assert_eq!("LD VF, 0x0C", Ops::LD_V(0xF, 0x0C).to_string());
+        assert_eq!("LD [I], V3", Ops::LDI(3).to_string());
+        assert_eq!("DW 0x8AAF", Ops::Data(0x8AAF).to_string());
+    }
 }
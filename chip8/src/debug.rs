@@ -0,0 +1,130 @@
+use crate::{
+    app::{AppState, ExecError},
+    chip8::{ch8_types::MEMORY_SIZE, Ops},
+};
+
+/// How a frame run by [Debugger::run_frame] ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameEnd {
+    /// All instructions ran and the timers ticked, holds the number of instructions executed
+    Completed(usize),
+
+    /// PC reached a breakpoint, the timers did not tick
+    Breakpoint(usize),
+}
+
+/// Breakpoints on memory addresses
+#[derive(Debug, Clone)]
+pub struct Debugger {
+    breakpoints: [u64; MEMORY_SIZE / 64],
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Self {
+            breakpoints: [0; MEMORY_SIZE / 64],
+        }
+    }
+}
+
+impl Debugger {
+    /// Sets or removes the breakpoint, returns true if it is set now
+    pub fn toggle_breakpoint(&mut self, address: usize) -> bool {
+        let address = address % MEMORY_SIZE;
+        self.breakpoints[address / 64] ^= 1 << (address % 64);
+        self.has_breakpoint(address)
+    }
+
+    pub fn has_breakpoint(&self, address: usize) -> bool {
+        let address = address % MEMORY_SIZE;
+        self.breakpoints[address / 64] & (1 << (address % 64)) != 0
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints = [0; MEMORY_SIZE / 64];
+    }
+
+    /// Like [AppState::try_run_frame], but stops as soon as PC reaches a breakpoint
+    ///
+    /// The instruction at the current PC always runs, so calling this again
+    /// continues from a breakpoint. Neither a breakpoint nor an error tick the timers.
+    pub fn run_frame(&self, app: &mut AppState, cycles: usize) -> Result<FrameEnd, ExecError> {
+        let mut i = 0;
+        while i < cycles {
+            app.try_step()?;
+            i += 1;
+            if self.has_breakpoint(app.pc) {
+                return Ok(FrameEnd::Breakpoint(i));
+            }
+            if app.is_waiting_for_vblank() {
+                break;
+            }
+        }
+
+        app.tick_timers();
+        Ok(FrameEnd::Completed(i))
+    }
+}
+
+/// Decodes the instructions from `address` on, the last one may be cut off at the end of memory
+pub fn disassemble(memory: &[u8], address: usize) -> impl Iterator<Item = (usize, Ops)> + '_ {
+    (address..memory.len().saturating_sub(1))
+        .step_by(2)
+        .map(move |a| (a, [memory[a], memory[a + 1]].into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        app::{AppState, ExecError},
+        chip8::Ops,
+    };
+
+    use super::{disassemble, Debugger, FrameEnd};
+
+    // 6001 LD V0, 0x01
+    // 7001 ADD V0, 0x01
+    // 1202 JP 0x202
+    const PROGRAM: [u8; 6] = [0x60, 0x01, 0x70, 0x01, 0x12, 0x02];
+
+    #[test]
+    fn breakpoints() {
+        let mut app = AppState::new(&PROGRAM);
+        let mut debugger = Debugger::default();
+
+        assert!(debugger.toggle_breakpoint(0x204));
+        assert_eq!(Ok(FrameEnd::Breakpoint(2)), debugger.run_frame(&mut app, 10));
+        assert_eq!(0x204, app.pc);
+        assert_eq!(2, app.registers()[0]);
+
+        // Continuing runs the instruction at the breakpoint
+        assert_eq!(Ok(FrameEnd::Breakpoint(2)), debugger.run_frame(&mut app, 10));
+        assert_eq!(3, app.registers()[0]);
+
+        assert!(!debugger.toggle_breakpoint(0x204));
+        assert_eq!(Ok(FrameEnd::Completed(10)), debugger.run_frame(&mut app, 10));
+    }
+
+    #[test]
+    fn unsupported() {
+        // 6001 LD V0, 0x01
+        // 0000
+        let mut app = AppState::new(&[0x60, 0x01, 0x00, 0x00]);
+        let debugger = Debugger::default();
+
+        let error = debugger.run_frame(&mut app, 10).unwrap_err();
+        assert!(matches!(error, ExecError::Unsupported(_)));
+        assert_eq!(0x202, app.pc);
+    }
+
+    #[test]
+    fn disassembly() {
+        let app = AppState::new(&PROGRAM);
+        let mut ops = disassemble(app.memory(), 0x200);
+
+        assert_eq!(Some((0x200, Ops::LD_V(0, 1))), ops.next());
+        assert_eq!(Some((0x202, Ops::ADD_V(0, 1))), ops.next());
+        assert_eq!(Some((0x204, Ops::JP(0x202))), ops.next());
+        assert_eq!(0xFFE, disassemble(app.memory(), 0xFF0).last().unwrap().0);
+    }
+}
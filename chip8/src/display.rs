@@ -30,6 +30,78 @@ pub const FONT: [u8; 0x50] = [
     0xF0, 0x80, 0xF0, 0x80, 0x80, // F
 ];
 
+/// Area of the VRAM which changed since it was last taken
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DirtyRect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl DirtyRect {
+    /// Rect covering the whole display
+    pub const FULL: DirtyRect = DirtyRect {
+        x: 0,
+        y: 0,
+        width: DISPLAY_WIDTH,
+        height: DISPLAY_HEIGHT,
+    };
+
+    /// Returns the smallest rect containing both rects
+    pub fn union(&self, other: &DirtyRect) -> DirtyRect {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        let right = (self.x + self.width).max(other.x + other.width);
+        let bottom = (self.y + self.height).max(other.y + other.height);
+
+        DirtyRect {
+            x,
+            y,
+            width: right - x,
+            height: bottom - y,
+        }
+    }
+}
+
+/// Keeps track of the VRAM areas touched by CLS, DRW and scroll operations,
+/// so renderers only have to repaint what actually changed
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DirtyTracker {
+    rect: Option<DirtyRect>,
+}
+
+impl DirtyTracker {
+    /// Marks the given area as changed, clipped to the display
+    pub fn mark(&mut self, x: usize, y: usize, width: usize, height: usize) {
+        if x >= DISPLAY_WIDTH || y >= DISPLAY_HEIGHT || width == 0 || height == 0 {
+            return;
+        }
+
+        let rect = DirtyRect {
+            x,
+            y,
+            width: width.min(DISPLAY_WIDTH - x),
+            height: height.min(DISPLAY_HEIGHT - y),
+        };
+
+        self.rect = Some(match self.rect {
+            Some(r) => r.union(&rect),
+            None => rect,
+        });
+    }
+
+    /// Marks the whole display as changed
+    pub fn mark_all(&mut self) {
+        self.rect = Some(DirtyRect::FULL);
+    }
+
+    /// Returns the changed area and resets the tracker
+    pub fn take(&mut self) -> Option<DirtyRect> {
+        self.rect.take()
+    }
+}
+
 /// Struct responsible for translation of the memory to string
 pub struct DisplayController;
 
@@ -39,23 +111,30 @@ impl DisplayController {
     }
 
     pub fn draw_onto(&self, obj: &mut VRAM, x: usize, y: usize, data: u8) -> u8 {
-        let mut changed = false;
-        //let index = global_xy_to_i(x, y);
-
-        //let offset = index as u8 % 8;
+        self.draw_row(obj, x, y, data, false)
+    }
 
-        let mut x: usize = x.into();
+    /// Like [DisplayController::draw_onto], but pixels past the right edge continue
+    /// on the left if `wrap` is set and are clipped otherwise, rows below the
+    /// display are not drawn
+    pub fn draw_row(&self, obj: &mut VRAM, x: usize, y: usize, data: u8, wrap: bool) -> u8 {
+        if y >= DISPLAY_HEIGHT {
+            return 0;
+        }
 
-        let mut pos: u8 = 0;
+        let mut pos: usize = 0;
         while pos != 8 {
-            let bitselect: u8 = 1 << 7 - pos;
-            let d = (data & bitselect) > 0;
+            let mut x = x + pos;
+            if x >= DISPLAY_WIDTH {
+                if !wrap {
+                    break;
+                }
+                x %= DISPLAY_WIDTH;
+            }
 
-            changed = obj[y][x] & d;
+            let bitselect: u8 = 1 << (7 - pos);
+            obj[y][x] ^= (data & bitselect) > 0;
 
-            obj[y][x] ^= d;
-
-            x += 1;
             pos += 1;
         }
 
@@ -64,6 +143,24 @@ impl DisplayController {
     }
 }
 
+/// Writes the VRAM as a plain (P1) portable bitmap, set pixels are written as 1
+pub fn write_pbm<W: core::fmt::Write>(obj: &VRAM, w: &mut W) -> core::fmt::Result {
+    writeln!(w, "P1")?;
+    writeln!(w, "{} {}", DISPLAY_WIDTH, DISPLAY_HEIGHT)?;
+
+    for row in obj.iter() {
+        for (x, pixel) in row.iter().enumerate() {
+            if x > 0 {
+                w.write_char(' ')?;
+            }
+            w.write_char(if *pixel { '1' } else { '0' })?;
+        }
+        w.write_char('\n')?;
+    }
+
+    Ok(())
+}
+
 pub trait Displayable {
     /// Clears the VRAM
     fn clear(&mut self);
@@ -87,7 +184,32 @@ mod tests {
         display::{global_xy_to_i, xy_to_i},
     };
 
-    use super::DisplayController;
+    use super::{DirtyRect, DirtyTracker, DisplayController};
+
+    #[test]
+    fn dirty_tracker_union() {
+        let mut tracker = DirtyTracker::default();
+        tracker.mark(2, 1, 8, 4);
+        tracker.mark(20, 10, 8, 2);
+
+        assert_eq!(
+            Some(DirtyRect { x: 2, y: 1, width: 26, height: 11 }),
+            tracker.take()
+        );
+        assert_eq!(None, tracker.take());
+    }
+
+    /// Sprites drawn at the right or bottom edge must not mark pixels outside the display
+    #[test]
+    fn dirty_tracker_clip() {
+        let mut tracker = DirtyTracker::default();
+        tracker.mark(60, 30, 8, 5);
+
+        assert_eq!(
+            Some(DirtyRect { x: 60, y: 30, width: 4, height: 2 }),
+            tracker.take()
+        );
+    }
 
     #[test]
     fn test_global_xy_to_i() {
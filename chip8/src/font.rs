@@ -0,0 +1,282 @@
+use core::fmt;
+
+use crate::{app::PROGRAM_START, chip8::ch8_types::MemoryAddress, display::FONT};
+
+/// Size of a complete set of 16 small (5 byte) glyphs
+pub const SMALL_FONT_SIZE: usize = 16 * SMALL_GLYPH_HEIGHT;
+pub const SMALL_GLYPH_HEIGHT: usize = 5;
+
+/// SUPER-CHIP large glyphs are 8x10 pixels
+pub const LARGE_GLYPH_HEIGHT: usize = 10;
+
+pub type SmallFont = [u8; SMALL_FONT_SIZE];
+
+/// The default location of the font, the interpreter area below 0x200 is unused otherwise
+pub const DEFAULT_FONT_ADDRESS: MemoryAddress = 0x50;
+
+/// https://github.com/JohnEarnest/Octo/blob/gh-pages/js/shared.js
+pub const COSMAC_VIP: SmallFont = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x60, 0x20, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0xA0, 0xA0, 0xF0, 0x20, 0x20, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x10, 0x10, 0x10, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xF0, 0x50, 0x70, 0x50, 0xF0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xF0, 0x50, 0x50, 0x50, 0xF0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+pub const DREAM_6800: SmallFont = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0, // 0
+    0x40, 0x40, 0x40, 0x40, 0x40, // 1
+    0xE0, 0x20, 0xE0, 0x80, 0xE0, // 2
+    0xE0, 0x20, 0xE0, 0x20, 0xE0, // 3
+    0x80, 0xA0, 0xA0, 0xE0, 0x20, // 4
+    0xE0, 0x80, 0xE0, 0x20, 0xE0, // 5
+    0xE0, 0x80, 0xE0, 0xA0, 0xE0, // 6
+    0xE0, 0x20, 0x20, 0x20, 0x20, // 7
+    0xE0, 0xA0, 0xE0, 0xA0, 0xE0, // 8
+    0xE0, 0xA0, 0xE0, 0x20, 0xE0, // 9
+    0xE0, 0xA0, 0xE0, 0xA0, 0xA0, // A
+    0xC0, 0xA0, 0xE0, 0xA0, 0xC0, // B
+    0xE0, 0x80, 0x80, 0x80, 0xE0, // C
+    0xC0, 0xA0, 0xA0, 0xA0, 0xC0, // D
+    0xE0, 0x80, 0xE0, 0x80, 0xE0, // E
+    0xE0, 0x80, 0xC0, 0x80, 0x80, // F
+];
+
+pub const ETI_660: SmallFont = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0, // 0
+    0x20, 0x20, 0x20, 0x20, 0x20, // 1
+    0xE0, 0x20, 0xE0, 0x80, 0xE0, // 2
+    0xE0, 0x20, 0xE0, 0x20, 0xE0, // 3
+    0xA0, 0xA0, 0xE0, 0x20, 0x20, // 4
+    0xE0, 0x80, 0xE0, 0x20, 0xE0, // 5
+    0xE0, 0x80, 0xE0, 0xA0, 0xE0, // 6
+    0xE0, 0x20, 0x20, 0x20, 0x20, // 7
+    0xE0, 0xA0, 0xE0, 0xA0, 0xE0, // 8
+    0xE0, 0xA0, 0xE0, 0x20, 0xE0, // 9
+    0xE0, 0xA0, 0xE0, 0xA0, 0xA0, // A
+    0x80, 0x80, 0xE0, 0xA0, 0xE0, // B
+    0xE0, 0x80, 0x80, 0x80, 0xE0, // C
+    0x20, 0x20, 0xE0, 0xA0, 0xE0, // D
+    0xE0, 0x80, 0xE0, 0x80, 0xE0, // E
+    0xE0, 0x80, 0xC0, 0x80, 0x80, // F
+];
+
+/// SUPER-CHIP 1.1 only has large glyphs for the digits 0 - 9
+pub const SCHIP_LARGE: [u8; 10 * LARGE_GLYPH_HEIGHT] = [
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+];
+
+/// Octo extends the SUPER-CHIP large font with the letters A - F
+pub const OCTO_LARGE: [u8; 16 * LARGE_GLYPH_HEIGHT] = [
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
+/// The known font variants of the different interpreters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FontSet {
+    CosmacVip,
+    Dream6800,
+    Eti660,
+    Schip,
+    Octo,
+
+    /// User supplied 5-byte glyphs for the digits 0 - F
+    Custom(SmallFont),
+}
+
+impl FontSet {
+    /// Returns the 5-byte glyphs used by `Fx29`
+    pub fn small(&self) -> &SmallFont {
+        match self {
+            FontSet::CosmacVip => &COSMAC_VIP,
+            FontSet::Dream6800 => &DREAM_6800,
+            FontSet::Eti660 => &ETI_660,
+            FontSet::Schip | FontSet::Octo => &FONT,
+            FontSet::Custom(glyphs) => glyphs,
+        }
+    }
+
+    /// Returns the 10-byte glyphs used by `Fx30`, if the set has any
+    pub fn large(&self) -> Option<&'static [u8]> {
+        match self {
+            FontSet::Schip => Some(&SCHIP_LARGE),
+            FontSet::Octo => Some(&OCTO_LARGE),
+            _ => None,
+        }
+    }
+}
+
+/// Selects the font set and where in memory it gets loaded
+///
+/// The large font (if any) is placed directly behind the small one. Both have to
+/// end below the program, see [FontConfig::fits].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FontConfig {
+    pub set: FontSet,
+    pub address: MemoryAddress,
+}
+
+impl Default for FontConfig {
+    fn default() -> Self {
+        Self {
+            set: FontSet::Schip,
+            address: DEFAULT_FONT_ADDRESS,
+        }
+    }
+}
+
+/// Reasons a font configuration can't be loaded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FontError {
+    /// The fonts at the address reach into the program, holds the first address past them
+    OverlapsProgram { address: MemoryAddress, end: usize },
+}
+
+impl fmt::Display for FontError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FontError::OverlapsProgram { address, end } => write!(
+                f,
+                "the font at 0x{:03X} ends at 0x{:03X}, past the program start at 0x{:03X}",
+                address, end, PROGRAM_START
+            ),
+        }
+    }
+}
+
+impl FontConfig {
+    pub fn new(set: FontSet, address: MemoryAddress) -> Self {
+        Self { set, address }
+    }
+
+    /// Bytes taken by the small and, if the set has one, the large font
+    pub fn size(&self) -> usize {
+        SMALL_FONT_SIZE + self.set.large().map_or(0, |large| large.len())
+    }
+
+    /// Returns true if both fonts end below the program
+    pub fn fits(&self) -> bool {
+        self.address as usize + self.size() <= PROGRAM_START
+    }
+
+    /// Returns the config if both fonts end below the program
+    pub fn validate(&self) -> Result<FontConfig, FontError> {
+        if self.fits() {
+            Ok(*self)
+        } else {
+            Err(FontError::OverlapsProgram {
+                address: self.address,
+                end: self.address as usize + self.size(),
+            })
+        }
+    }
+
+    /// Moves the fonts down as far as needed to end below the program, for callers
+    /// that rather relocate the font than reject it
+    pub fn clamped(&self) -> Self {
+        let highest = (PROGRAM_START - self.size()) as MemoryAddress;
+        Self {
+            address: self.address.min(highest),
+            ..*self
+        }
+    }
+
+    /// Address of the small glyph for the lowest nibble of the given digit
+    pub fn small_glyph_address(&self, digit: u8) -> MemoryAddress {
+        self.address
+            .wrapping_add((digit & 0xF) as MemoryAddress * SMALL_GLYPH_HEIGHT as MemoryAddress)
+    }
+
+    /// Address of the large glyph for the lowest nibble of the given digit
+    pub fn large_glyph_address(&self, digit: u8) -> MemoryAddress {
+        self.address
+            .wrapping_add(SMALL_FONT_SIZE as MemoryAddress)
+            .wrapping_add((digit & 0xF) as MemoryAddress * LARGE_GLYPH_HEIGHT as MemoryAddress)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FontConfig, FontError, FontSet, SMALL_FONT_SIZE};
+
+    #[test]
+    fn small_glyph_address() {
+        let font = FontConfig::new(FontSet::CosmacVip, 0x000);
+        assert_eq!(0x000, font.small_glyph_address(0x0));
+        assert_eq!(0x04B, font.small_glyph_address(0xF));
+
+        // Only the lowest nibble selects the glyph
+        assert_eq!(0x005, font.small_glyph_address(0x21));
+    }
+
+    #[test]
+    fn large_glyph_address() {
+        let font = FontConfig::default();
+        assert_eq!(0x0A0, font.large_glyph_address(0x0));
+        assert_eq!(0x0AA, font.large_glyph_address(0x1));
+    }
+
+    #[test]
+    fn fits_below_program() {
+        assert!(FontConfig::default().fits());
+        assert!(FontConfig::new(FontSet::CosmacVip, 0x1B0).fits());
+        assert!(!FontConfig::new(FontSet::Octo, 0x1B0).fits());
+        assert!(!FontConfig::new(FontSet::Schip, 0xFFFF).fits());
+        assert_eq!(
+            Err(FontError::OverlapsProgram { address: 0x1B0, end: 0x2A0 }),
+            FontConfig::new(FontSet::Octo, 0x1B0).validate()
+        );
+
+        let font = FontConfig::new(FontSet::Octo, 0xFFFF).clamped();
+        assert_eq!(0x200 - 0x50 - 0xA0, font.address);
+        assert!(font.fits());
+        assert_eq!(0x1FF, font.large_glyph_address(0xF) + 9);
+
+        // Fonts that fit stay where they are
+        assert_eq!(0x50, FontConfig::default().clamped().address);
+    }
+
+    #[test]
+    fn custom_font() {
+        let glyphs = [0xAA; SMALL_FONT_SIZE];
+        let set = FontSet::Custom(glyphs);
+
+        assert_eq!(&glyphs, set.small());
+        assert_eq!(None, set.large());
+    }
+}
@@ -0,0 +1,112 @@
+//! Golden-frame test harness
+//!
+//! Runs a ROM for a fixed number of frames with a scripted input sequence and
+//! compares the resulting framebuffer against a PBM stored in `chip8/golden`.
+//! Other output like captured audio is compared byte by byte.
+//! Set `CHIP8_BLESS=1` to (re)write the golden files from the current output.
+extern crate std;
+
+use std::{format, string::String};
+
+use crate::{
+    app::AppState,
+    chip8::ch8_types::{DISPLAY_HEIGHT, DISPLAY_WIDTH, VRAM},
+    display::write_pbm,
+};
+
+/// Instructions executed per frame by [run_script]
+pub const CYCLES_PER_FRAME: usize = 10;
+
+/// A key press or release applied at the start of the given frame
+#[derive(Debug, Clone, Copy)]
+pub struct InputEvent {
+    pub frame: usize,
+    pub key: u8,
+    pub pressed: bool,
+}
+
+impl InputEvent {
+    pub fn down(frame: usize, key: u8) -> Self {
+        Self { frame, key, pressed: true }
+    }
+}
+
+/// Runs the program for the given number of frames, applying the input events as it goes
+pub fn run_script(prog: &[u8], frames: usize, input: &[InputEvent]) -> AppState {
+    let mut app = AppState::new(prog);
+
+    let mut frame = 0;
+    while frame < frames {
+        for event in input.iter().filter(|e| e.frame == frame) {
+            app.set_key(event.key, event.pressed);
+        }
+
+        app.run_frame(CYCLES_PER_FRAME);
+        frame += 1;
+    }
+
+    app
+}
+
+/// Parses a plain (P1) portable bitmap with the dimensions of the display
+pub fn parse_pbm(data: &str) -> VRAM {
+    let mut tokens = data
+        .lines()
+        .map(|l| l.split('#').next().unwrap_or(""))
+        .flat_map(|l| l.split_whitespace());
+
+    assert_eq!(Some("P1"), tokens.next(), "golden file is not a plain PBM");
+    assert_eq!(Some(format!("{}", DISPLAY_WIDTH).as_str()), tokens.next());
+    assert_eq!(Some(format!("{}", DISPLAY_HEIGHT).as_str()), tokens.next());
+
+    let mut vram = [[false; DISPLAY_WIDTH]; DISPLAY_HEIGHT];
+    for row in vram.iter_mut() {
+        for pixel in row.iter_mut() {
+            *pixel = tokens.next().expect("golden file is truncated") == "1";
+        }
+    }
+
+    vram
+}
+
+/// Compares the framebuffer against `golden/<name>.pbm`
+pub fn assert_golden(app: &AppState, name: &str) {
+    let path = format!("{}/golden/{}.pbm", env!("CARGO_MANIFEST_DIR"), name);
+
+    let mut actual = String::new();
+    write_pbm(&app.vram, &mut actual).unwrap();
+
+    if std::env::var_os("CHIP8_BLESS").is_some() {
+        std::fs::write(&path, &actual).unwrap();
+        return;
+    }
+
+    let expected = std::fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("could not read golden file {}: {}", path, e));
+
+    let mut golden = AppState::new(&[]);
+    golden.vram = parse_pbm(&expected);
+
+    assert_eq!(
+        golden.frame_hash(),
+        app.frame_hash(),
+        "frame does not match {}, got:\n{}",
+        path,
+        actual
+    );
+}
+
+/// Compares the data against the file `golden/<name>`
+pub fn assert_golden_file(data: &[u8], name: &str) {
+    let path = format!("{}/golden/{}", env!("CARGO_MANIFEST_DIR"), name);
+
+    if std::env::var_os("CHIP8_BLESS").is_some() {
+        std::fs::write(&path, data).unwrap();
+        return;
+    }
+
+    let expected = std::fs::read(&path)
+        .unwrap_or_else(|e| panic!("could not read golden file {}: {}", path, e));
+
+    assert!(expected == data, "output does not match {}", path);
+}
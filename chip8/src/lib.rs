@@ -1,5 +1,16 @@
-#![no_std]
+#![cfg_attr(not(feature = "std"), no_std)]
 pub mod chip8;
 pub mod app;
+pub mod audio;
+pub mod debug;
 pub mod display;
-mod memory;
\ No newline at end of file
+pub mod font;
+pub mod phosphor;
+pub mod render;
+mod memory;
+
+#[cfg(any(feature = "std", test))]
+pub mod wav;
+
+#[cfg(test)]
+mod golden;
\ No newline at end of file
@@ -23,12 +23,22 @@ impl Memory {
         }
     }
 
+    /// Addresses past the end of memory wrap around to the start
     pub fn get_u8(&mut self, address: usize) -> &mut u8 {
-        &mut self.memory[address]
+        &mut self.memory[address % MEMORY_SIZE]
     }
 
+    pub fn as_bytes(&self) -> &ch8_types::Memory {
+        &self.memory
+    }
+
+    pub fn as_bytes_mut(&mut self) -> &mut ch8_types::Memory {
+        &mut self.memory
+    }
+
+    /// An instruction at the last address continues at the start of memory
     pub fn get_instruction(&mut self, address: usize) -> [u8; 2] {
-        let (fb, sb) = (self.memory[address], self.memory[address + 1]);
+        let (fb, sb) = (self.memory[address % MEMORY_SIZE], self.memory[(address + 1) % MEMORY_SIZE]);
         [fb, sb]
     }
 }
@@ -59,6 +69,15 @@ mod tests {
         assert_eq!([0x41, 0x42], mem.get_instruction(0x200))
     }
 
+    #[test]
+    fn get_instruction_wraps() {
+        let mut mem = Memory::default();
+        mem.memory[0xFFF] = 0x12;
+        mem.memory[0x000] = 0x34;
+
+        assert_eq!([0x12, 0x34], mem.get_instruction(0xFFF))
+    }
+
     #[test]
     fn modify_instruction() {
         let mut mem = Memory::default();
@@ -70,4 +89,12 @@ mod tests {
         assert_eq!(0x50, *mem.get_u8(0x200));
         //assert_eq!([0x41, 0x42], mem.get_instruction(0x200))
     }
+
+    #[test]
+    fn wrap_around() {
+        let mut mem = Memory::default();
+        *mem.get_u8(0x1001) = 0x50;
+
+        assert_eq!(0x50, mem.memory[0x001]);
+    }
 }
\ No newline at end of file
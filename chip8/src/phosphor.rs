@@ -0,0 +1,173 @@
+use crate::chip8::ch8_types::{DISPLAY_HEIGHT, DISPLAY_WIDTH, VRAM};
+
+/// Brightness of every pixel, 0 is off and 255 fully lit
+pub type Intensity = [[u8; DISPLAY_WIDTH]; DISPLAY_HEIGHT];
+
+/// Size of the buffer needed by [Phosphor::write_rgba]
+pub const RGBA_FRAME_SIZE: usize = DISPLAY_WIDTH * DISPLAY_HEIGHT * 4;
+
+/// How the VRAM gets composited into the presented frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PhosphorMode {
+    /// Pixels are presented exactly as they are in the VRAM
+    Off,
+
+    /// Pixels that got turned off fade out by the given amount of brightness per frame
+    Decay(u8),
+
+    /// A pixel is lit if it was set in the current or the previous frame,
+    /// hides the flicker of sprites that are erased and redrawn every frame
+    Blend,
+}
+
+impl PhosphorMode {
+    /// All modes with the names they can be selected by
+    pub const PRESETS: [(&'static str, PhosphorMode); 3] = [
+        ("off", PhosphorMode::Off),
+        ("decay", PhosphorMode::Decay(0x40)),
+        ("blend", PhosphorMode::Blend),
+    ];
+
+    /// Looks up a preset by its name
+    pub fn from_name(name: &str) -> Option<PhosphorMode> {
+        PhosphorMode::PRESETS
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, mode)| *mode)
+    }
+
+    /// Returns the preset after this one, for toggles cycling through them
+    pub fn next(&self) -> PhosphorMode {
+        let i = PhosphorMode::PRESETS.iter().position(|(_, mode)| mode == self);
+        PhosphorMode::PRESETS[i.map_or(0, |i| (i + 1) % PhosphorMode::PRESETS.len())].1
+    }
+}
+
+/// Post-processing layer emulating the persistence of a CRT phosphor
+///
+/// The emulation itself is not affected, the layer only reads the VRAM once per frame.
+#[derive(Debug, Clone)]
+pub struct Phosphor {
+    pub mode: PhosphorMode,
+    frame: Intensity,
+    previous: VRAM,
+}
+
+impl Phosphor {
+    pub fn new(mode: PhosphorMode) -> Self {
+        Self {
+            mode,
+            frame: [[0; DISPLAY_WIDTH]; DISPLAY_HEIGHT],
+            previous: [[false; DISPLAY_WIDTH]; DISPLAY_HEIGHT],
+        }
+    }
+
+    /// Composites the VRAM into the frame, has to be called once per emulated frame
+    pub fn update(&mut self, vram: &VRAM) {
+        let mut y = 0;
+        while y < DISPLAY_HEIGHT {
+            let mut x = 0;
+            while x < DISPLAY_WIDTH {
+                let lit = vram[y][x];
+                let current = self.frame[y][x];
+
+                self.frame[y][x] = match self.mode {
+                    _ if lit => 0xFF,
+                    PhosphorMode::Off => 0,
+                    PhosphorMode::Decay(amount) => current.saturating_sub(amount),
+                    PhosphorMode::Blend if self.previous[y][x] => 0xFF,
+                    PhosphorMode::Blend => 0,
+                };
+
+                x += 1;
+            }
+            y += 1;
+        }
+
+        self.previous = *vram;
+    }
+
+    /// Returns the composited grayscale frame
+    pub fn frame(&self) -> &Intensity {
+        &self.frame
+    }
+
+    /// Writes the frame as grayscale RGBA pixels into the buffer,
+    /// which must hold at least [RGBA_FRAME_SIZE] bytes
+    pub fn write_rgba(&self, out: &mut [u8]) {
+        for (i, value) in self.frame.iter().flatten().enumerate() {
+            let pixel = &mut out[i * 4..i * 4 + 4];
+            pixel.copy_from_slice(&[*value, *value, *value, 0xFF]);
+        }
+    }
+}
+
+impl Default for Phosphor {
+    fn default() -> Self {
+        Self::new(PhosphorMode::Off)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chip8::ch8_types::{DISPLAY_HEIGHT, DISPLAY_WIDTH, VRAM};
+
+    use super::{Phosphor, PhosphorMode, RGBA_FRAME_SIZE};
+
+    #[test]
+    fn decay() {
+        let mut vram: VRAM = [[false; DISPLAY_WIDTH]; DISPLAY_HEIGHT];
+        let mut phosphor = Phosphor::new(PhosphorMode::Decay(0x60));
+
+        vram[0][0] = true;
+        phosphor.update(&vram);
+        assert_eq!(0xFF, phosphor.frame()[0][0]);
+
+        vram[0][0] = false;
+        phosphor.update(&vram);
+        assert_eq!(0x9F, phosphor.frame()[0][0]);
+        phosphor.update(&vram);
+        phosphor.update(&vram);
+        assert_eq!(0x00, phosphor.frame()[0][0]);
+    }
+
+    /// A sprite that is only visible every other frame must stay lit
+    #[test]
+    fn blend() {
+        let mut vram: VRAM = [[false; DISPLAY_WIDTH]; DISPLAY_HEIGHT];
+        let mut phosphor = Phosphor::new(PhosphorMode::Blend);
+
+        vram[1][2] = true;
+        phosphor.update(&vram);
+        vram[1][2] = false;
+        phosphor.update(&vram);
+        assert_eq!(0xFF, phosphor.frame()[1][2]);
+
+        phosphor.update(&vram);
+        assert_eq!(0x00, phosphor.frame()[1][2]);
+    }
+
+    #[test]
+    fn presets() {
+        assert_eq!(Some(PhosphorMode::Blend), PhosphorMode::from_name("blend"));
+        assert_eq!(None, PhosphorMode::from_name("glow"));
+
+        assert_eq!(PhosphorMode::Decay(0x40), PhosphorMode::Off.next());
+        assert_eq!(PhosphorMode::Off, PhosphorMode::Blend.next());
+        assert_eq!(PhosphorMode::Off, PhosphorMode::Decay(1).next());
+    }
+
+    #[test]
+    fn write_rgba() {
+        let mut vram: VRAM = [[false; DISPLAY_WIDTH]; DISPLAY_HEIGHT];
+        let mut phosphor = Phosphor::default();
+        vram[0][1] = true;
+        phosphor.update(&vram);
+
+        let mut out = [0u8; RGBA_FRAME_SIZE];
+        phosphor.write_rgba(&mut out);
+
+        assert_eq!([0x00, 0x00, 0x00, 0xFF], out[0..4]);
+        assert_eq!([0xFF, 0xFF, 0xFF, 0xFF], out[4..8]);
+    }
+}
@@ -0,0 +1,463 @@
+use crate::{
+    chip8::ch8_types::{DISPLAY_HEIGHT, DISPLAY_WIDTH, VRAM},
+    phosphor::Intensity,
+};
+
+/// A colour as red, green and blue components
+pub type Rgb = [u8; 3];
+
+/// Colours used to present the frame
+///
+/// Index 0 is the background and index 1 the colour of lit pixels. XO-CHIP draws on
+/// two bit planes, index 2 is used for pixels only set on the second plane and
+/// index 3 for pixels set on both.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette {
+    pub colors: [Rgb; 4],
+}
+
+impl Palette {
+    pub const CLASSIC: Palette = Palette {
+        colors: [[0x00, 0x00, 0x00], [0xFF, 0xFF, 0xFF], [0xAA, 0xAA, 0xAA], [0x55, 0x55, 0x55]],
+    };
+
+    pub const AMBER: Palette = Palette {
+        colors: [[0x1A, 0x0F, 0x00], [0xFF, 0xB0, 0x00], [0xB3, 0x6B, 0x00], [0x66, 0x3D, 0x00]],
+    };
+
+    pub const GREEN_PHOSPHOR: Palette = Palette {
+        colors: [[0x00, 0x14, 0x00], [0x33, 0xFF, 0x33], [0x1F, 0xA3, 0x1F], [0x0F, 0x52, 0x0F]],
+    };
+
+    /// The default colours of the Octo IDE
+    pub const OCTO: Palette = Palette {
+        colors: [[0x99, 0x66, 0x00], [0xFF, 0xCC, 0x00], [0xFF, 0x66, 0x00], [0x66, 0x22, 0x00]],
+    };
+
+    pub const HIGH_CONTRAST: Palette = Palette {
+        colors: [[0x00, 0x00, 0x00], [0xFF, 0xFF, 0x00], [0x00, 0xFF, 0xFF], [0xFF, 0x00, 0xFF]],
+    };
+
+    /// All presets with the names they can be selected by
+    pub const PRESETS: [(&'static str, Palette); 5] = [
+        ("classic", Palette::CLASSIC),
+        ("amber", Palette::AMBER),
+        ("green", Palette::GREEN_PHOSPHOR),
+        ("octo", Palette::OCTO),
+        ("high-contrast", Palette::HIGH_CONTRAST),
+    ];
+
+    /// Looks up a preset by its name
+    pub fn from_name(name: &str) -> Option<Palette> {
+        Palette::PRESETS
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, palette)| *palette)
+    }
+
+    /// Returns the colour for the bit planes set at a pixel (bit 0 is the first plane)
+    pub fn plane_color(&self, planes: u8) -> Rgb {
+        self.colors[(planes & 0b11) as usize]
+    }
+
+    /// Blends between background and foreground, used for partially lit pixels
+    pub fn shade(&self, intensity: u8) -> Rgb {
+        let [bg, fg] = [self.colors[0], self.colors[1]];
+        let mut out = [0; 3];
+
+        for (c, (b, f)) in out.iter_mut().zip(bg.iter().zip(fg.iter())) {
+            let (b, f, i) = (*b as u16, *f as u16, intensity as u16);
+            *c = ((b * (0xFF - i) + f * i) / 0xFF) as u8;
+        }
+
+        out
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::CLASSIC
+    }
+}
+
+/// Upscaling filter applied when rendering a frame to RGBA
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Filter {
+    /// Every pixel becomes a square block of the given size
+    Nearest(usize),
+
+    /// AdvMAME2x, smooths diagonal edges while doubling the size
+    Scale2x,
+
+    /// AdvMAME3x, smooths diagonal edges while tripling the size
+    Scale3x,
+
+    /// A simplified hq2x, like [Filter::Scale2x] but the corners on diagonal edges
+    /// are blended between both colours instead of replaced
+    Hq2x,
+
+    /// Like [Filter::Nearest], but the last row of every block is drawn at half
+    /// brightness to imitate the gaps between CRT scanlines
+    Scanlines(usize),
+}
+
+impl Filter {
+    /// EPX is the same algorithm as Scale2x
+    pub const EPX: Filter = Filter::Scale2x;
+
+    /// All presets with the names they can be selected by
+    pub const PRESETS: [(&'static str, Filter); 5] = [
+        ("none", Filter::Nearest(1)),
+        ("scale2x", Filter::Scale2x),
+        ("scale3x", Filter::Scale3x),
+        ("hq2x", Filter::Hq2x),
+        ("scanlines", Filter::Scanlines(3)),
+    ];
+
+    /// Looks up a preset by its name
+    pub fn from_name(name: &str) -> Option<Filter> {
+        Filter::PRESETS
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, filter)| *filter)
+    }
+
+    /// Factor the frame grows by in both directions
+    pub fn scale(&self) -> usize {
+        match self {
+            Filter::Nearest(n) | Filter::Scanlines(n) => *n,
+            Filter::Scale2x | Filter::Hq2x => 2,
+            Filter::Scale3x => 3,
+        }
+    }
+
+    /// Width and height of the rendered frame in pixels
+    pub fn output_size(&self) -> (usize, usize) {
+        (DISPLAY_WIDTH * self.scale(), DISPLAY_HEIGHT * self.scale())
+    }
+
+    /// Size of the RGBA buffer needed by [render]
+    pub fn buffer_len(&self) -> usize {
+        let (w, h) = self.output_size();
+        w * h * 4
+    }
+}
+
+/// Converts the VRAM into a frame with fully lit and dark pixels
+pub fn intensity_from_vram(vram: &VRAM) -> Intensity {
+    let mut frame = [[0; DISPLAY_WIDTH]; DISPLAY_HEIGHT];
+
+    for (row, vram_row) in frame.iter_mut().zip(vram.iter()) {
+        for (value, pixel) in row.iter_mut().zip(vram_row.iter()) {
+            *value = if *pixel { 0xFF } else { 0x00 };
+        }
+    }
+
+    frame
+}
+
+/// Fills `out` with one byte per pixel holding the bit planes set at the pixel,
+/// row by row like [render_planes] expects
+pub fn planes_from_vram(vram: &VRAM, out: &mut [u8]) {
+    for (plane, pixel) in out.iter_mut().zip(vram.iter().flatten()) {
+        *plane = *pixel as u8;
+    }
+}
+
+/// Colours a frame of any resolution into the RGBA buffer without scaling
+///
+/// Every byte of `planes` holds the bit planes set at one pixel (see [Palette::plane_color]),
+/// so the same path presents 64x32, 128x64 SUPER-CHIP and two plane XO-CHIP frames.
+/// `out` must hold 4 bytes per pixel.
+pub fn render_planes(planes: &[u8], palette: &Palette, out: &mut [u8]) {
+    for (pixel, plane) in out.chunks_exact_mut(4).zip(planes.iter()) {
+        let [r, g, b] = palette.plane_color(*plane);
+        pixel.copy_from_slice(&[r, g, b, 0xFF]);
+    }
+}
+
+/// Upscales the frame and colours it with the palette into the RGBA buffer,
+/// which must hold at least [Filter::buffer_len] bytes
+pub fn render(frame: &Intensity, filter: Filter, palette: &Palette, out: &mut [u8]) {
+    let scale = filter.scale();
+    let (width, _) = filter.output_size();
+
+    let mut y = 0;
+    while y < DISPLAY_HEIGHT {
+        let mut x = 0;
+        while x < DISPLAY_WIDTH {
+            let mut block = [0u8; 9];
+            let block = match filter {
+                Filter::Scale2x => {
+                    scale2x(frame, x, y, &mut block);
+                    &block[..4]
+                }
+                Filter::Scale3x => {
+                    scale3x(frame, x, y, &mut block);
+                    &block[..]
+                }
+                Filter::Hq2x => {
+                    hq2x(frame, x, y, &mut block);
+                    &block[..4]
+                }
+                _ => &block[..0],
+            };
+
+            let mut by = 0;
+            while by < scale {
+                let mut bx = 0;
+                while bx < scale {
+                    let value = match filter {
+                        Filter::Nearest(_) => frame[y][x],
+                        Filter::Scanlines(_) if by == scale - 1 && scale > 1 => frame[y][x] / 2,
+                        Filter::Scanlines(_) => frame[y][x],
+                        Filter::Scale2x | Filter::Scale3x | Filter::Hq2x => block[by * scale + bx],
+                    };
+
+                    let [r, g, b] = palette.shade(value);
+                    let i = ((y * scale + by) * width + x * scale + bx) * 4;
+                    out[i..i + 4].copy_from_slice(&[r, g, b, 0xFF]);
+
+                    bx += 1;
+                }
+                by += 1;
+            }
+
+            x += 1;
+        }
+        y += 1;
+    }
+}
+
+/// Returns the 3x3 neighbourhood of the pixel (A B C / D E F / G H I),
+/// pixels outside of the frame repeat the edge
+fn neighbours(frame: &Intensity, x: usize, y: usize) -> [u8; 9] {
+    let (left, right) = (x.saturating_sub(1), (x + 1).min(DISPLAY_WIDTH - 1));
+    let (up, down) = (y.saturating_sub(1), (y + 1).min(DISPLAY_HEIGHT - 1));
+
+    [
+        frame[up][left],
+        frame[up][x],
+        frame[up][right],
+        frame[y][left],
+        frame[y][x],
+        frame[y][right],
+        frame[down][left],
+        frame[down][x],
+        frame[down][right],
+    ]
+}
+
+/// https://www.scale2x.it/algorithm
+fn scale2x(frame: &Intensity, x: usize, y: usize, out: &mut [u8; 9]) {
+    let [_, b, _, d, e, f, _, h, _] = neighbours(frame, x, y);
+
+    out[0] = if d == b && b != f && d != h { d } else { e };
+    out[1] = if b == f && b != d && f != h { f } else { e };
+    out[2] = if d == h && d != b && h != f { d } else { e };
+    out[3] = if h == f && d != h && b != f { f } else { e };
+}
+
+/// The corners [scale2x] would replace get the average of both colours
+fn hq2x(frame: &Intensity, x: usize, y: usize, out: &mut [u8; 9]) {
+    let e = frame[y][x];
+    scale2x(frame, x, y, out);
+
+    for corner in out[..4].iter_mut() {
+        *corner = ((*corner as u16 + e as u16) / 2) as u8;
+    }
+}
+
+/// https://www.scale2x.it/algorithm
+fn scale3x(frame: &Intensity, x: usize, y: usize, out: &mut [u8; 9]) {
+    let [a, b, c, d, e, f, g, h, i] = neighbours(frame, x, y);
+
+    let db = d == b && b != f && d != h;
+    let bf = b == f && b != d && f != h;
+    let dh = d == h && d != b && h != f;
+    let hf = h == f && d != h && b != f;
+
+    out[0] = if db { d } else { e };
+    out[1] = if (db && e != c) || (bf && e != a) { b } else { e };
+    out[2] = if bf { f } else { e };
+    out[3] = if (db && e != g) || (dh && e != a) { d } else { e };
+    out[4] = e;
+    out[5] = if (bf && e != i) || (hf && e != c) { f } else { e };
+    out[6] = if dh { d } else { e };
+    out[7] = if (dh && e != i) || (hf && e != g) { h } else { e };
+    out[8] = if hf { f } else { e };
+}
+
+/// Writes an RGBA buffer as a plain (P3) portable pixmap, the alpha channel is dropped
+pub fn write_ppm<W: core::fmt::Write>(
+    rgba: &[u8],
+    width: usize,
+    height: usize,
+    w: &mut W,
+) -> core::fmt::Result {
+    writeln!(w, "P3")?;
+    writeln!(w, "{} {}", width, height)?;
+    writeln!(w, "255")?;
+
+    for row in rgba.chunks(width * 4).take(height) {
+        for (x, pixel) in row.chunks(4).enumerate() {
+            if x > 0 {
+                w.write_char(' ')?;
+            }
+            write!(w, "{} {} {}", pixel[0], pixel[1], pixel[2])?;
+        }
+        w.write_char('\n')?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        chip8::ch8_types::{DISPLAY_HEIGHT, DISPLAY_WIDTH, VRAM},
+        phosphor::Intensity,
+    };
+
+    use super::{intensity_from_vram, planes_from_vram, render, render_planes, Filter, Palette};
+
+    /// Returns the gray value of the rendered pixel
+    fn pixel(out: &[u8], filter: Filter, x: usize, y: usize) -> u8 {
+        let (width, _) = filter.output_size();
+        out[(y * width + x) * 4]
+    }
+
+    #[test]
+    fn palette_from_name() {
+        assert_eq!(Some(Palette::OCTO), Palette::from_name("octo"));
+        assert_eq!(None, Palette::from_name("octarine"));
+    }
+
+    #[test]
+    fn filter_from_name() {
+        assert_eq!(Some(Filter::Scale3x), Filter::from_name("scale3x"));
+        assert_eq!(None, Filter::from_name("hq9x"));
+    }
+
+    #[test]
+    fn palette_shade() {
+        let palette = Palette::OCTO;
+        assert_eq!(palette.colors[0], palette.shade(0x00));
+        assert_eq!(palette.colors[1], palette.shade(0xFF));
+        assert_eq!([0xCC, 0x99, 0x00], palette.shade(0x80));
+    }
+
+    #[test]
+    fn palette_planes() {
+        let palette = Palette::HIGH_CONTRAST;
+        assert_eq!([0x00, 0xFF, 0xFF], palette.plane_color(0b10));
+        assert_eq!([0xFF, 0x00, 0xFF], palette.plane_color(0b11));
+    }
+
+    #[test]
+    fn render_plane_frame() {
+        let mut vram: VRAM = [[false; DISPLAY_WIDTH]; DISPLAY_HEIGHT];
+        vram[1][2] = true;
+        let mut planes = [0; DISPLAY_WIDTH * DISPLAY_HEIGHT];
+        planes_from_vram(&vram, &mut planes);
+        assert_eq!(1, planes[DISPLAY_WIDTH + 2]);
+
+        // A 2x1 XO-CHIP frame with both planes set on the second pixel
+        let palette = Palette::HIGH_CONTRAST;
+        let mut out = [0; 8];
+        render_planes(&[0b00, 0b11], &palette, &mut out);
+        assert_eq!([0x00, 0x00, 0x00, 0xFF, 0xFF, 0x00, 0xFF, 0xFF], out);
+    }
+
+    #[test]
+    fn render_palette() {
+        let mut frame: Intensity = [[0; DISPLAY_WIDTH]; DISPLAY_HEIGHT];
+        frame[0][1] = 0xFF;
+
+        let mut out = [0u8; DISPLAY_WIDTH * DISPLAY_HEIGHT * 4];
+        render(&frame, Filter::Nearest(1), &Palette::AMBER, &mut out);
+
+        assert_eq!([0x1A, 0x0F, 0x00, 0xFF], out[0..4]);
+        assert_eq!([0xFF, 0xB0, 0x00, 0xFF], out[4..8]);
+    }
+
+    #[test]
+    fn nearest() {
+        let mut frame: Intensity = [[0; DISPLAY_WIDTH]; DISPLAY_HEIGHT];
+        frame[0][1] = 0xFF;
+
+        let filter = Filter::Nearest(3);
+        let mut out = [0u8; DISPLAY_WIDTH * DISPLAY_HEIGHT * 4 * 9];
+        render(&frame, filter, &Palette::CLASSIC, &mut out);
+
+        assert_eq!(0x00, pixel(&out, filter, 2, 0));
+        assert_eq!(0xFF, pixel(&out, filter, 3, 0));
+        assert_eq!(0xFF, pixel(&out, filter, 5, 2));
+        assert_eq!(0x00, pixel(&out, filter, 6, 2));
+        assert_eq!(0xFF, out[3]);
+    }
+
+    #[test]
+    fn scanlines() {
+        let mut vram: VRAM = [[false; DISPLAY_WIDTH]; DISPLAY_HEIGHT];
+        vram[0][0] = true;
+
+        let filter = Filter::Scanlines(2);
+        let mut out = [0u8; DISPLAY_WIDTH * DISPLAY_HEIGHT * 4 * 4];
+        render(&intensity_from_vram(&vram), filter, &Palette::CLASSIC, &mut out);
+
+        assert_eq!(0xFF, pixel(&out, filter, 1, 0));
+        assert_eq!(0x7F, pixel(&out, filter, 1, 1));
+    }
+
+    /// A diagonal line gets its stairs filled in
+    #[test]
+    fn scale2x_diagonal() {
+        let mut frame: Intensity = [[0; DISPLAY_WIDTH]; DISPLAY_HEIGHT];
+        frame[0][0] = 0xFF;
+        frame[1][1] = 0xFF;
+
+        let filter = Filter::Scale2x;
+        let mut out = [0u8; DISPLAY_WIDTH * DISPLAY_HEIGHT * 4 * 4];
+        render(&frame, filter, &Palette::CLASSIC, &mut out);
+
+        // The dark pixel at (1, 0) gets its lower left corner lit
+        assert_eq!(0x00, pixel(&out, filter, 2, 0));
+        assert_eq!(0xFF, pixel(&out, filter, 2, 1));
+        assert_eq!(0x00, pixel(&out, filter, 3, 1));
+    }
+
+    #[test]
+    fn hq2x_blends_diagonal() {
+        let mut frame: Intensity = [[0; DISPLAY_WIDTH]; DISPLAY_HEIGHT];
+        frame[0][0] = 0xFF;
+        frame[1][1] = 0xFF;
+
+        let filter = Filter::Hq2x;
+        let mut out = [0u8; DISPLAY_WIDTH * DISPLAY_HEIGHT * 4 * 4];
+        render(&frame, filter, &Palette::CLASSIC, &mut out);
+
+        assert_eq!(0x00, pixel(&out, filter, 2, 0));
+        assert_eq!(0x7F, pixel(&out, filter, 2, 1));
+        assert_eq!(0xFF, pixel(&out, filter, 0, 0));
+        assert_eq!(0x00, pixel(&out, filter, 3, 1));
+    }
+
+    #[test]
+    fn scale3x_keeps_single_pixel() {
+        let mut frame: Intensity = [[0; DISPLAY_WIDTH]; DISPLAY_HEIGHT];
+        frame[1][1] = 0xFF;
+
+        let filter = Filter::Scale3x;
+        let mut out = [0u8; DISPLAY_WIDTH * DISPLAY_HEIGHT * 4 * 9];
+        render(&frame, filter, &Palette::CLASSIC, &mut out);
+
+        let mut y = 3;
+        while y < 6 {
+            assert_eq!(0xFF, pixel(&out, filter, 3, y));
+            assert_eq!(0xFF, pixel(&out, filter, 5, y));
+            assert_eq!(0x00, pixel(&out, filter, 6, y));
+            y += 1;
+        }
+    }
+}
@@ -0,0 +1,171 @@
+//! WAV export of the synthesized audio, needs the `std` feature
+extern crate std;
+
+use std::{
+    io::{self, Write},
+    vec::Vec,
+};
+
+use crate::{
+    app::AppState,
+    audio::{AudioGenerator, TICKS_PER_SECOND},
+};
+
+/// Records the samples of every tick of a run
+#[derive(Debug, Clone)]
+pub struct AudioCapture {
+    pub generator: AudioGenerator,
+    samples: Vec<i16>,
+
+    /// Samples per second not yet written because a tick only holds whole samples
+    remainder: u32,
+}
+
+impl AudioCapture {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            generator: AudioGenerator::new(sample_rate),
+            samples: Vec::new(),
+            remainder: 0,
+        }
+    }
+
+    /// Synthesizes one tick of audio from the current state of the emulator,
+    /// has to be called once per frame
+    ///
+    /// Sample rates that aren't a multiple of 60 get an extra sample every few
+    /// ticks, so the recording keeps the length of the run.
+    pub fn capture_tick(&mut self, app: &AppState) {
+        let total = self.remainder + self.generator.sample_rate;
+        self.remainder = total % TICKS_PER_SECOND;
+
+        let start = self.samples.len();
+        self.samples.resize(start + (total / TICKS_PER_SECOND) as usize, 0);
+
+        self.generator.update(app);
+        self.generator.fill_i16(app.is_sounding() as u8, &mut self.samples[start..]);
+    }
+
+    pub fn samples(&self) -> &[i16] {
+        &self.samples
+    }
+
+    /// Writes the captured samples as a WAV file
+    pub fn write_wav<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        write_wav(w, self.generator.sample_rate, &self.samples)
+    }
+}
+
+/// Writes mono 16-bit PCM samples as a WAV file
+pub fn write_wav<W: Write>(w: &mut W, sample_rate: u32, samples: &[i16]) -> io::Result<()> {
+    const CHANNELS: u16 = 1;
+    const BITS_PER_SAMPLE: u16 = 16;
+    const BLOCK_ALIGN: u16 = CHANNELS * BITS_PER_SAMPLE / 8;
+
+    let data_size = (samples.len() * BLOCK_ALIGN as usize) as u32;
+
+    // RIFF header
+    w.write_all(b"RIFF")?;
+    w.write_all(&(36 + data_size).to_le_bytes())?;
+    w.write_all(b"WAVE")?;
+
+    // Format chunk
+    w.write_all(b"fmt ")?;
+    w.write_all(&16u32.to_le_bytes())?;
+    w.write_all(&1u16.to_le_bytes())?; // PCM
+    w.write_all(&CHANNELS.to_le_bytes())?;
+    w.write_all(&sample_rate.to_le_bytes())?;
+    w.write_all(&(sample_rate * BLOCK_ALIGN as u32).to_le_bytes())?;
+    w.write_all(&BLOCK_ALIGN.to_le_bytes())?;
+    w.write_all(&BITS_PER_SAMPLE.to_le_bytes())?;
+
+    // Data chunk
+    w.write_all(b"data")?;
+    w.write_all(&data_size.to_le_bytes())?;
+    for sample in samples {
+        w.write_all(&sample.to_le_bytes())?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{app::AppState, golden};
+
+    use super::{std::vec::Vec, write_wav, AudioCapture};
+
+    #[test]
+    fn header() {
+        let mut out = Vec::new();
+        write_wav(&mut out, 8000, &[0, 1, -1]).unwrap();
+
+        assert_eq!(44 + 6, out.len());
+        assert_eq!(b"RIFF", &out[0..4]);
+        assert_eq!(42u32.to_le_bytes(), out[4..8]);
+        assert_eq!(8000u32.to_le_bytes(), out[24..28]);
+        assert_eq!(6u32.to_le_bytes(), out[40..44]);
+        assert_eq!([0x01, 0x00, 0xFF, 0xFF], out[46..50]);
+    }
+
+    /// Beeps for half of the run
+    #[test]
+    fn capture_beep() {
+        // 600F LD V0, 0x0F
+        // F018 LD ST, V0
+        // 1204 JP 0x204
+        let prg = [0x60, 0x0F, 0xF0, 0x18, 0x12, 0x04];
+        let mut app = AppState::new(&prg);
+        let mut capture = AudioCapture::new(8000);
+
+        let mut frame = 0;
+        while frame < 30 {
+            app.run_frame(golden::CYCLES_PER_FRAME);
+            capture.capture_tick(&app);
+            frame += 1;
+        }
+
+        // Ticks start at multiples of 133.3 samples, the beep covers all 15 of them
+        let tick = |n: usize| n * 8000 / 60;
+        let samples = capture.samples();
+        assert_eq!(4000, samples.len());
+        assert!(samples[..tick(1)].iter().any(|s| *s != 0));
+        assert!(samples[tick(14)..tick(15)].iter().any(|s| *s != 0));
+        assert!(samples[tick(16)..].iter().all(|s| *s == 0));
+
+        let mut wav = Vec::new();
+        capture.write_wav(&mut wav).unwrap();
+        golden::assert_golden_file(&wav, "beep.wav");
+    }
+
+    /// A beep of a single tick still gets captured
+    #[test]
+    fn capture_short_beep() {
+        // 6001 LD V0, 0x01
+        // F018 LD ST, V0
+        // 1204 JP 0x204
+        let prg = [0x60, 0x01, 0xF0, 0x18, 0x12, 0x04];
+        let mut app = AppState::new(&prg);
+        let mut capture = AudioCapture::new(8000);
+
+        app.run_frame(golden::CYCLES_PER_FRAME);
+        capture.capture_tick(&app);
+
+        assert!(capture.samples().iter().any(|s| *s != 0));
+    }
+
+    /// A second at 22050 Hz has 367.5 samples per tick
+    #[test]
+    fn capture_length() {
+        let app = AppState::new(&[0x12, 0x00]);
+        let mut capture = AudioCapture::new(22050);
+
+        let mut frame = 0;
+        while frame < 60 {
+            capture.capture_tick(&app);
+            frame += 1;
+        }
+
+        assert_eq!(22050, capture.samples().len());
+    }
+}